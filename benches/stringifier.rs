@@ -0,0 +1,102 @@
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::{DynamicImage, Rgb, RgbImage};
+use rayon::ThreadPoolBuilder;
+use stringify::{image_utils::ColorDistance, ArtGenerator, Board, Stringifier};
+
+/// Rings of the palette colors, so every color has chords worth running.
+fn source_image() -> DynamicImage {
+    let palette = palette();
+    DynamicImage::ImageRgb8(RgbImage::from_fn(400, 400, |x, y| {
+        let (dx, dy) = (x as f32 - 200.0, y as f32 - 200.0);
+        let ring = (dx.hypot(dy) / 25.0) as usize;
+        palette[ring % palette.len()]
+    }))
+}
+
+fn palette() -> Vec<Rgb<u8>> {
+    vec![Rgb([20, 9, 23]), Rgb([107, 96, 122]), Rgb([214, 186, 189])]
+}
+
+/// Steps timed from the start of every run.
+const STEPS: u64 = 100;
+
+/// One step of the greedy search: scoring every chord from every thread's
+/// current nail and laying the best one. Timed on one thread and on every
+/// core, to show what scoring in parallel buys.
+fn bench_step(c: &mut Criterion) {
+    let src_img = source_image();
+    let mut group = c.benchmark_group("stringifier_step");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(STEPS));
+
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut thread_counts = vec![1, cores];
+    thread_counts.dedup();
+
+    for threads in thread_counts {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+
+        for nail_count in [100, 200] {
+            let id = BenchmarkId::new(format!("{}_threads", threads), nail_count);
+
+            // fresh runs every time, a finished run has nothing left to step.
+            // Runs are built inside the pool, so its threads do the scoring
+            group.bench_function(id, |b| {
+                b.iter_custom(|iters| {
+                    pool.install(|| {
+                        let mut elapsed = Duration::ZERO;
+                        for _ in 0..iters {
+                            let board = Rc::new(Board::new(500.0, nail_count, 0.4).unwrap());
+                            let algo = Box::new(Stringifier::new(
+                                &board,
+                                &src_img,
+                                &palette(),
+                                ColorDistance::Rgb,
+                            ));
+                            let mut generator = ArtGenerator::new(board, algo);
+
+                            let start = Instant::now();
+                            for _ in 0..STEPS {
+                                generator.step();
+                            }
+                            elapsed += start.elapsed();
+                        }
+                        elapsed
+                    })
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// Setting up a run, where the work is spread over all cores: working out
+/// every chord of the board and scoring it against the dithered image.
+fn bench_setup(c: &mut Criterion) {
+    let src_img = source_image();
+    let mut group = c.benchmark_group("stringifier_setup");
+    group.sample_size(10);
+
+    for nail_count in [100, 200] {
+        group.bench_function(BenchmarkId::from_parameter(nail_count), |b| {
+            b.iter(|| {
+                let board = Board::new(500.0, nail_count, 0.4).unwrap();
+                Stringifier::new(&board, &src_img, &palette(), ColorDistance::Rgb)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_step, bench_setup);
+criterion_main!(benches);
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, error::Error};
 
 use image::Rgb;
 
@@ -6,7 +6,48 @@ use crate::board::Nail;
 
 pub type StrandPositions = HashMap<Rgb<u8>, Nail>;
 
-pub(crate) trait ArtAlgo {
+/// How a thread mixes with the threads already running over a pixel.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum BlendMode {
+    /// A thread only fills the part of a pixel earlier threads left
+    /// uncovered, so the first color over a pixel wins
+    #[default]
+    Cover,
+    /// Every thread lies on top of the ones before it and lets them show
+    /// through where it is translucent, mixing the colors optically
+    Stack,
+}
+
+pub trait ArtAlgo {
     fn initial_nails(&self) -> StrandPositions;
     fn next_nail(&mut self, nails: &StrandPositions) -> Option<(Rgb<u8>, Nail)>;
+
+    /// The thread colors in palette order, the order ties between colors are
+    /// broken in.
+    fn palette(&self) -> Vec<Rgb<u8>>;
+
+    /// How much of a pixel a single thread covering it hides. Below `1.0`,
+    /// overlapping threads build up on top of each other.
+    fn line_opacity(&self) -> f32 {
+        1.0
+    }
+
+    /// How the threads this algorithm lays down should be drawn.
+    fn blend_mode(&self) -> BlendMode {
+        BlendMode::Cover
+    }
+
+    /// Color of the board [`BlendMode::Stack`] threads are laid on, which
+    /// shows through them. `None` for a transparent board.
+    fn background(&self) -> Option<Rgb<u8>> {
+        None
+    }
+
+    /// Everything the algorithm has learned between steps, to carry on with
+    /// from a [`crate::Checkpoint`].
+    fn save_state(&self) -> serde_json::Value;
+
+    /// Carry on from a state saved by [`ArtAlgo::save_state`] of an algorithm
+    /// built for the same job.
+    fn restore_state(&mut self, state: serde_json::Value) -> Result<(), Box<dyn Error>>;
 }
@@ -1,13 +1,31 @@
 use std::{collections::HashMap, rc::Rc};
 
-use image::{GenericImage, GenericImageView, Pixel, Rgb, Rgba};
+use image::{GenericImage, GenericImageView, Rgb, Rgba};
 
 use crate::{
-    art_algo::ArtAlgo,
+    art_algo::{ArtAlgo, BlendMode, StrandPositions},
     board::{Board, Nail},
 };
 
-type NailPattern = Vec<(Rgb<u8>, Nail)>;
+pub type NailPattern = Vec<(Rgb<u8>, Nail)>;
+
+/// Every nail of `pattern` as `(color, from, to)`, in pattern order, where
+/// `from` is the nail the thread of that color comes from and `None` where the
+/// thread is tied on.
+pub fn pattern_steps(
+    pattern: &[(Rgb<u8>, Nail)],
+) -> impl Iterator<Item = (Rgb<u8>, Option<Nail>, Nail)> + '_ {
+    let mut last_nails: HashMap<Rgb<u8>, Nail> = HashMap::new();
+
+    pattern
+        .iter()
+        .map(move |(color, nail)| (*color, last_nails.insert(*color, *nail), *nail))
+}
+
+/// Every thread segment of `pattern` as `(color, from, to)`, in pattern order.
+pub fn segments(pattern: &[(Rgb<u8>, Nail)]) -> impl Iterator<Item = (Rgb<u8>, Nail, Nail)> + '_ {
+    pattern_steps(pattern).filter_map(|(color, from, to)| Some((color, from?, to)))
+}
 
 pub struct ArtGenerator {
     board: Rc<Board>,
@@ -17,14 +35,33 @@ pub struct ArtGenerator {
     art: image::DynamicImage,
 }
 
+/// The art before any thread, the algorithm's background for stacked threads
+/// so they mix with it as the algorithm expects, transparent otherwise.
+fn blank_art(board: &Board, algo: &dyn ArtAlgo) -> image::DynamicImage {
+    let (width, height) = (board.dimensions().width(), board.dimensions().height());
+
+    match (algo.blend_mode(), algo.background()) {
+        (BlendMode::Stack, Some(Rgb([r, g, b]))) => image::DynamicImage::ImageRgba8(
+            image::RgbaImage::from_pixel(width, height, Rgba([r, g, b, 255])),
+        ),
+        _ => image::DynamicImage::new_rgba8(width, height),
+    }
+}
+
+/// Sort `(color, nail)` pairs by the palette index of their color.
+pub fn sort_by_palette(nails: &mut [(Rgb<u8>, Nail)], palette: &[Rgb<u8>]) {
+    nails.sort_by_key(|(color, _)| palette.iter().position(|c| c == color));
+}
+
 impl ArtGenerator {
     pub fn new(board: Rc<Board>, algo: Box<dyn ArtAlgo>) -> Self {
         let nails = algo.initial_nails();
 
-        let pattern: NailPattern = nails.iter().map(|(color, nail)| (*color, *nail)).collect();
+        let mut pattern: NailPattern = nails.iter().map(|(color, nail)| (*color, *nail)).collect();
+        // in palette order every run, whatever order the map hands them out in
+        sort_by_palette(&mut pattern, &algo.palette());
 
-        let art =
-            image::DynamicImage::new_rgba8(board.dimensions().width(), board.dimensions().height());
+        let art = blank_art(&board, algo.as_ref());
 
         Self {
             board,
@@ -35,15 +72,50 @@ impl ArtGenerator {
         }
     }
 
+    /// Pick up a run where it was left, with `algo` already restored to the
+    /// state it was in after laying `pattern`. The art is redrawn from the
+    /// pattern.
+    pub fn resume(
+        board: Rc<Board>,
+        algo: Box<dyn ArtAlgo>,
+        current_nails: StrandPositions,
+        pattern: NailPattern,
+    ) -> Self {
+        let art = blank_art(&board, algo.as_ref());
+
+        let mut generator = Self {
+            board,
+            algo,
+            current_nails,
+            pattern,
+            art,
+        };
+        generator.redraw();
+
+        generator
+    }
+
+    /// Swap in a pattern changed outside of the algorithm, e.g. by
+    /// [`crate::refine::refine`], and redraw the art. The algorithm no longer
+    /// matches the pattern, so stepping on from here makes little sense.
+    pub fn replace_pattern(&mut self, pattern: NailPattern) {
+        self.current_nails = pattern.iter().copied().collect();
+        self.pattern = pattern;
+        self.art = blank_art(&self.board, self.algo.as_ref());
+        self.redraw();
+    }
+
+    fn redraw(&mut self) {
+        for (color, from, to) in segments(&self.pattern).collect::<Vec<_>>() {
+            self.paint_path(from, to, color);
+        }
+    }
+
     pub fn step(&mut self) -> Option<(Rgb<u8>, Nail)> {
         // TODO get rid of this clone
         let nail_choice = self.algo.next_nail(&self.current_nails);
 
-        if nail_choice.is_none() {
-            return None;
-        }
-
-        let (color, next_nail) = nail_choice.unwrap();
+        let (color, next_nail) = nail_choice?;
         let last_nail = *self.current_nails.get(&color).unwrap();
 
         self.paint_path(last_nail, next_nail, color);
@@ -54,19 +126,45 @@ impl ArtGenerator {
         nail_choice
     }
 
+    /// Draw a thread according to the algorithm's [`BlendMode`]. A thread
+    /// covers [`ArtAlgo::line_opacity`] of the pixels it fully runs over.
     fn paint_path(&mut self, last_nail: Nail, next_nail: Nail, color: Rgb<u8>) {
-        let path = self
-            .board
-            .paths()
-            .get(&last_nail)
-            .unwrap()
-            .get(&next_nail)
-            .unwrap();
-
-        for (x, y) in path {
-            if self.art.get_pixel(*x, *y) == Rgba([0, 0, 0, 0]) {
-                self.art.put_pixel(*x, *y, color.to_rgba());
+        let chord = self.board.chords().get(last_nail, next_nail).unwrap();
+        let opacity = self.algo.line_opacity();
+        let blend_mode = self.algo.blend_mode();
+
+        for (x, y, coverage) in chord.coverage() {
+            let Rgba([r, g, b, a]) = self.art.get_pixel(x, y);
+            let covered = a as f32 / 255.0;
+
+            // how much of the pixel the old and the new color end up showing on
+            let (shown, added) = match blend_mode {
+                BlendMode::Cover => (covered, (coverage * opacity).min(1.0 - covered)),
+                BlendMode::Stack => {
+                    let added = coverage * opacity;
+                    (covered * (1.0 - added), added)
+                }
+            };
+            if added <= 0.0 {
+                continue;
             }
+
+            let total = shown + added;
+            let mix = |old: u8, new: u8| {
+                ((old as f32 * shown + new as f32 * added) / total).round() as u8
+            };
+            let Rgb([new_r, new_g, new_b]) = color;
+
+            self.art.put_pixel(
+                x,
+                y,
+                Rgba([
+                    mix(r, new_r),
+                    mix(g, new_g),
+                    mix(b, new_b),
+                    (total * 255.0).round() as u8,
+                ]),
+            );
         }
     }
 
@@ -74,6 +172,48 @@ impl ArtGenerator {
         &self.pattern
     }
 
+    /// Length of thread used so far for each color, measured nail centre to
+    /// nail centre on the physical board.
+    pub fn thread_lengths_mm(&self) -> Vec<(Rgb<u8>, f64)> {
+        let mut lengths: Vec<(Rgb<u8>, f64)> = Vec::new();
+
+        for (color, from, to) in pattern_steps(&self.pattern) {
+            let length = from.map_or(0.0, |from| self.board.distance_mm(from, to));
+
+            match lengths.iter_mut().find(|(c, _)| *c == color) {
+                Some((_, total)) => *total += length,
+                None => lengths.push((color, length)),
+            }
+        }
+
+        lengths
+    }
+
+    /// Where the thread of every color currently is.
+    pub fn current_nails(&self) -> &StrandPositions {
+        &self.current_nails
+    }
+
+    /// See [`ArtAlgo::save_state`].
+    pub fn algo_state(&self) -> serde_json::Value {
+        self.algo.save_state()
+    }
+
+    /// See [`ArtAlgo::palette`].
+    pub fn palette(&self) -> Vec<Rgb<u8>> {
+        self.algo.palette()
+    }
+
+    /// See [`ArtAlgo::line_opacity`].
+    pub fn line_opacity(&self) -> f32 {
+        self.algo.line_opacity()
+    }
+
+    /// See [`ArtAlgo::blend_mode`].
+    pub fn blend_mode(&self) -> BlendMode {
+        self.algo.blend_mode()
+    }
+
     pub fn art(&self) -> &image::DynamicImage {
         &self.art
     }
@@ -0,0 +1,253 @@
+use image::Rgb;
+
+use crate::{
+    art_algo::{ArtAlgo, StrandPositions},
+    board::Nail,
+    score_cache::ROUNDING,
+    stringifier::{Move, Stringifier},
+};
+
+/// A [`Stringifier`] that looks further ahead than the next chord, so a color
+/// doesn't end up at a nail with only poor chords left.
+///
+/// Each step searches `depth` moves ahead, keeping the `width` sequences of
+/// moves with the highest summed score at every depth, then makes the first
+/// move of the best one. With a width and depth of 1 it picks the same moves
+/// as the plain stringifier.
+pub struct BeamSearch {
+    stringifier: Stringifier,
+    width: usize,
+    depth: usize,
+}
+
+/// Moves made one after the other and their summed score.
+#[derive(Debug, Clone)]
+struct Branch {
+    moves: Vec<Move>,
+    score: f64,
+}
+
+impl BeamSearch {
+    pub fn new(stringifier: Stringifier, width: usize, depth: usize) -> Self {
+        assert!(width > 0 && depth > 0);
+
+        Self {
+            stringifier,
+            width,
+            depth,
+        }
+    }
+
+    /// The first move of the best sequence found from `nails`.
+    fn best_move(&mut self, nails: &StrandPositions) -> Option<Move> {
+        let mut beam = vec![Branch {
+            moves: Vec::new(),
+            score: 0.0,
+        }];
+
+        for _ in 0..self.depth {
+            let mut next_beam = Vec::new();
+            let mut grown = false;
+
+            for branch in &beam {
+                let continuations = self.continuations(nails, branch);
+                if continuations.is_empty() {
+                    // a finished sequence still competes with the longer ones
+                    next_beam.push(branch.clone());
+                } else {
+                    next_beam.extend(continuations);
+                    grown = true;
+                }
+            }
+
+            if !grown {
+                break;
+            }
+            beam = best_branches(next_beam, self.width);
+        }
+
+        beam.first()?.moves.first().copied()
+    }
+
+    /// The best `width` ways to carry on from `branch`. Lays its threads to
+    /// score what could follow, then takes them up again.
+    fn continuations(&mut self, nails: &StrandPositions, branch: &Branch) -> Vec<Branch> {
+        let mut positions = nails.clone();
+        let mut changes = Vec::with_capacity(branch.moves.len());
+        for step in &branch.moves {
+            changes.push(self.stringifier.cover(step.from, step.to));
+            positions.insert(step.color, step.to);
+        }
+
+        let continuations = self
+            .stringifier
+            .moves(&positions)
+            .map(|step| {
+                let mut moves = branch.moves.clone();
+                moves.push(step);
+                Branch {
+                    moves,
+                    score: branch.score + step.score,
+                }
+            })
+            .collect();
+
+        for changes in changes.into_iter().rev() {
+            self.stringifier.uncover(changes);
+        }
+
+        best_branches(continuations, self.width)
+    }
+}
+
+/// The `width` highest scoring branches. Equal branches, as well as ones
+/// only rounding apart, stay in palette and board order.
+fn best_branches(mut branches: Vec<Branch>, width: usize) -> Vec<Branch> {
+    let mut best = Vec::with_capacity(width.min(branches.len()));
+
+    while best.len() < width && !branches.is_empty() {
+        let i = (1..branches.len()).fold(0, |best, i| {
+            if branches[i].score > branches[best].score + ROUNDING {
+                i
+            } else {
+                best
+            }
+        });
+        best.push(branches.remove(i));
+    }
+
+    best
+}
+
+impl ArtAlgo for BeamSearch {
+    fn initial_nails(&self) -> StrandPositions {
+        self.stringifier.initial_nails()
+    }
+
+    fn palette(&self) -> Vec<Rgb<u8>> {
+        self.stringifier.palette()
+    }
+
+    fn next_nail(&mut self, nails: &StrandPositions) -> Option<(Rgb<u8>, Nail)> {
+        let best_move = self.best_move(nails)?;
+        self.stringifier.cover(best_move.from, best_move.to);
+
+        Some((best_move.color, best_move.to))
+    }
+
+    fn line_opacity(&self) -> f32 {
+        self.stringifier.line_opacity()
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        self.stringifier.save_state()
+    }
+
+    fn restore_state(
+        &mut self,
+        state: serde_json::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.stringifier.restore_state(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::board::{BlockedChords, Board, NailPosition};
+    use crate::image_utils::ColorDistance;
+    use image::{DynamicImage, RgbImage};
+
+    const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+    const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+    // NWWWN
+    // W   B
+    // W   B
+    // B   B
+    // NWWWN
+    //
+    // the top edge is the best chord from the top left, but leaves the thread
+    // at a nail with nothing white left to reach
+    fn trap() -> (Board, DynamicImage) {
+        let positions = [(0.5, 0.5), (0.5, 4.5), (4.5, 0.5), (4.5, 4.5)]
+            .map(|(x, y)| NailPosition(x, y))
+            .to_vec();
+        let board = Board::from_nails(5.0, 5.0, positions, 1.0, BlockedChords::Allow).unwrap();
+
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(5, 5, |x, y| {
+            let top_or_bottom = (y == 0 || y == 4) && (1..4).contains(&x);
+            let left = x == 0 && (y == 1 || y == 2);
+            if top_or_bottom || left {
+                WHITE
+            } else {
+                BLACK
+            }
+        }));
+
+        (board, img)
+    }
+
+    #[test]
+    fn test_looks_past_the_greedy_choice() {
+        let (board, img) = trap();
+        let nails = HashMap::from([(WHITE, Nail(0, 0))]);
+
+        let mut greedy = Stringifier::new(&board, &img, &[WHITE, BLACK], ColorDistance::Rgb);
+        assert_eq!(greedy.next_nail(&nails), Some((WHITE, Nail(4, 0))));
+
+        let stringifier = Stringifier::new(&board, &img, &[WHITE, BLACK], ColorDistance::Rgb);
+        let mut beam_search = BeamSearch::new(stringifier, 2, 2);
+        assert_eq!(beam_search.next_nail(&nails), Some((WHITE, Nail(0, 4))));
+        assert_eq!(
+            beam_search.next_nail(&HashMap::from([(WHITE, Nail(0, 4))])),
+            Some((WHITE, Nail(4, 4)))
+        );
+    }
+
+    #[test]
+    fn test_narrow_and_shallow_is_greedy() {
+        let board = Board::new(40.0, 24, 1.0).unwrap();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 40, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                BLACK
+            } else {
+                WHITE
+            }
+        }));
+        let palette = [BLACK, WHITE];
+
+        let mut greedy = Stringifier::new(&board, &img, &palette, ColorDistance::Rgb);
+        let mut beam_search = BeamSearch::new(
+            Stringifier::new(&board, &img, &palette, ColorDistance::Rgb),
+            1,
+            1,
+        );
+
+        let mut nails = greedy.initial_nails();
+        assert_eq!(beam_search.initial_nails(), nails);
+        for _ in 0..30 {
+            let step = greedy.next_nail(&nails);
+            assert_eq!(beam_search.next_nail(&nails), step);
+            let Some((color, nail)) = step else {
+                break;
+            };
+            nails.insert(color, nail);
+        }
+    }
+
+    #[test]
+    fn test_best_branches_within_rounding() {
+        let branch = |score| Branch {
+            moves: Vec::new(),
+            score,
+        };
+        let branches = vec![branch(1.0), branch(2.0 - 1e-9), branch(2.0), branch(1.5)];
+
+        let best = best_branches(branches, 3);
+        let scores = best.iter().map(|b| b.score).collect::<Vec<_>>();
+        assert_eq!(scores, vec![2.0 - 1e-9, 2.0, 1.5]);
+    }
+}
@@ -0,0 +1,405 @@
+use std::{collections::HashMap, sync::Arc};
+
+use image::{DynamicImage, Rgb};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    art_algo::{ArtAlgo, BlendMode, StrandPositions},
+    board::{Board, Nail},
+    chords::Chords,
+    image_utils::ColorDistance,
+    util::{parse_hex_color, to_color, to_hex_color, Color, ColorPalette},
+};
+
+/// Multi-color string art where overlapping threads mix optically instead of
+/// the first color over a pixel winning.
+///
+/// Keeps a model of the finished board: every pixel starts out as the board's
+/// background and each thread crossing it is laid on top with `opacity` times
+/// its coverage, letting the threads below show through. Each step runs the
+/// chord, of any color, that brings the model closest to the source image,
+/// measured by `color_distance` on colors with channels from 0 to 1. Stops
+/// once no chord improves it by more than `min_improvement`.
+pub struct Blender {
+    initial_nails: StrandPositions,
+    palette: Vec<Rgb<u8>>,
+    nails: Vec<Nail>,
+    chords: Arc<Chords>,
+    /// The source image as [`ColorDistance::coordinates`]
+    target: Vec<Color>,
+    canvas: Vec<Color>,
+    /// How far every pixel of the canvas is from the target, converting the
+    /// canvas only where it changes
+    errors: Vec<f32>,
+    /// How far every pixel would be from the target with one more thread of
+    /// each color fully covering it, one entry per palette color
+    covered_errors: Vec<f32>,
+    width: u32,
+    opacity: f32,
+    min_improvement: f32,
+    background: Rgb<u8>,
+    color_distance: ColorDistance,
+    /// See [`ColorDistance::scale`]
+    distance_scale: f32,
+    last_nails: HashMap<Rgb<u8>, Nail>,
+}
+
+/// See [`ArtAlgo::save_state`]. Last nails are keyed by hex color.
+#[derive(Serialize, Deserialize)]
+struct BlenderState {
+    canvas: Vec<Color>,
+    last_nails: Vec<(String, Nail)>,
+}
+
+impl Blender {
+    pub fn new(
+        board: &Board,
+        src_img: &DynamicImage,
+        color_palette: ColorPalette,
+        background: Rgb<u8>,
+        opacity: f32,
+        min_improvement: f32,
+        color_distance: ColorDistance,
+    ) -> Self {
+        assert!(opacity > 0.0 && opacity <= 1.0);
+
+        let scaled_img = board.scale_image(src_img, None).to_rgb8();
+        let width = scaled_img.width();
+
+        let target = scaled_img
+            .pixels()
+            .map(|pixel| color_distance.coordinates(Rgb(to_color(pixel))))
+            .collect::<Vec<_>>();
+        let canvas = vec![to_color(&background); target.len()];
+
+        // pixels off a shaped board don't count either way
+        let chords = board.chords_on_board();
+
+        let mut blender = Self {
+            initial_nails: HashMap::new(),
+            palette: color_palette.to_vec(),
+            nails: board.nails().to_vec(),
+            chords,
+            target,
+            canvas,
+            errors: Vec::new(),
+            covered_errors: Vec::new(),
+            width,
+            opacity,
+            min_improvement,
+            background,
+            color_distance,
+            distance_scale: color_distance.scale(),
+            last_nails: HashMap::new(),
+        };
+        blender.update_errors(0..blender.canvas.len());
+        blender.initial_nails = blender.starting_nails(board.nails(), color_palette);
+
+        blender
+    }
+
+    /// For every color, one end of the chord that improves the blank board most.
+    fn starting_nails(&self, nails: &[Nail], color_palette: ColorPalette) -> StrandPositions {
+        let mut starting_nails = HashMap::new();
+
+        for color in color_palette {
+            let mut best_improvement = self.min_improvement;
+
+            for i in 0..nails.len() {
+                for j in i + 1..nails.len() {
+                    let Some(improvement) = self.improvement(*color, nails[i], nails[j]) else {
+                        continue;
+                    };
+
+                    if improvement > best_improvement {
+                        best_improvement = improvement;
+                        starting_nails.insert(*color, nails[i]);
+                    }
+                }
+            }
+        }
+
+        starting_nails
+    }
+
+    /// How much closer to the source image laying a thread of `color` from
+    /// `from` to `to` would bring the board, `None` if there is no such chord.
+    fn improvement(&self, color: Rgb<u8>, from: Nail, to: Nail) -> Option<f32> {
+        let chord = self.chords.get(from, to)?;
+        let thread = to_color(&color);
+        let color_index = self.palette.iter().position(|c| *c == color)?;
+
+        let improvement = chord
+            .iter()
+            .map(|(i, coverage)| {
+                let after = if coverage == 1.0 {
+                    self.covered_errors[i * self.palette.len() + color_index]
+                } else {
+                    let after = blend(self.canvas[i], thread, self.opacity * coverage);
+                    self.distance(after, self.target[i])
+                };
+
+                self.errors[i] - after
+            })
+            .sum();
+
+        Some(improvement)
+    }
+
+    /// Work out [`Blender::errors`] and [`Blender::covered_errors`] again for
+    /// the pixels in `pixels`, which only changes where the canvas did.
+    fn update_errors(&mut self, pixels: impl IntoIterator<Item = usize>) {
+        let colors = self.palette.len();
+        self.errors.resize(self.canvas.len(), 0.0);
+        self.covered_errors.resize(self.canvas.len() * colors, 0.0);
+
+        for i in pixels {
+            self.errors[i] = self.distance(self.canvas[i], self.target[i]);
+            for (c, color) in self.palette.iter().enumerate() {
+                let after = blend(self.canvas[i], to_color(color), self.opacity);
+                self.covered_errors[i * colors + c] = self.distance(after, self.target[i]);
+            }
+        }
+    }
+
+    /// Distance from `color` to `target`, given as coordinates, scaled to
+    /// RGB so `min_improvement` means the same for every kind.
+    fn distance(&self, color: Color, target: Color) -> f32 {
+        let distance = self.color_distance;
+        distance.between(distance.coordinates(Rgb(color)), target) * self.distance_scale
+    }
+
+    fn lay_thread(&mut self, color: Rgb<u8>, from: Nail, to: Nail) {
+        let chords = Arc::clone(&self.chords);
+        let chord = chords.get(from, to).unwrap();
+        let thread = to_color(&color);
+
+        for (i, coverage) in chord.iter() {
+            self.canvas[i] = blend(self.canvas[i], thread, self.opacity * coverage);
+        }
+        self.update_errors(chord.iter().map(|(i, _)| i));
+    }
+
+    /// The modelled look of the board so far.
+    pub fn canvas(&self) -> DynamicImage {
+        let width = self.width;
+        let height = self.canvas.len() as u32 / width;
+
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+            let [r, g, b] = self.canvas[(y * width + x) as usize];
+            Rgb([r, g, b].map(|channel| (channel * 255.0).round() as u8))
+        }))
+    }
+}
+
+impl ArtAlgo for Blender {
+    fn initial_nails(&self) -> StrandPositions {
+        self.initial_nails.clone()
+    }
+
+    fn palette(&self) -> Vec<Rgb<u8>> {
+        self.palette.clone()
+    }
+
+    fn next_nail(&mut self, nails: &StrandPositions) -> Option<(Rgb<u8>, Nail)> {
+        let mut best_improvement = self.min_improvement;
+        let mut best_move = None;
+
+        // in palette and board order, so equal moves go to the lowest color
+        // index and then the lowest nail index
+        for color in &self.palette {
+            let Some(nail) = nails.get(color) else {
+                continue;
+            };
+
+            for next_nail in &self.nails {
+                // going straight back along the same chord only doubles it up
+                if self.last_nails.get(color) == Some(next_nail) {
+                    continue;
+                }
+
+                let Some(improvement) = self.improvement(*color, *nail, *next_nail) else {
+                    continue;
+                };
+                if improvement > best_improvement {
+                    best_improvement = improvement;
+                    best_move = Some((*color, *next_nail));
+                }
+            }
+        }
+
+        let (color, next_nail) = best_move?;
+        self.lay_thread(color, nails[&color], next_nail);
+        self.last_nails.insert(color, nails[&color]);
+
+        best_move
+    }
+
+    fn line_opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    fn blend_mode(&self) -> BlendMode {
+        BlendMode::Stack
+    }
+
+    fn background(&self) -> Option<Rgb<u8>> {
+        Some(self.background)
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        let mut last_nails = self
+            .last_nails
+            .iter()
+            .map(|(color, nail)| (to_hex_color(color), *nail))
+            .collect::<Vec<_>>();
+        last_nails.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        serde_json::to_value(BlenderState {
+            canvas: self.canvas.clone(),
+            last_nails,
+        })
+        .unwrap()
+    }
+
+    fn restore_state(
+        &mut self,
+        state: serde_json::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let state: BlenderState = serde_json::from_value(state)?;
+
+        if state.canvas.len() != self.canvas.len() {
+            return Err(format!(
+                "canvas has {} pixels, the board has {}",
+                state.canvas.len(),
+                self.canvas.len()
+            )
+            .into());
+        }
+
+        self.canvas = state.canvas;
+        self.update_errors(0..self.canvas.len());
+        self.last_nails = state
+            .last_nails
+            .iter()
+            .map(|(color, nail)| Ok((parse_hex_color(color)?, *nail)))
+            .collect::<Result<_, String>>()?;
+        Ok(())
+    }
+}
+
+/// `thread` laid over `below`, hiding `alpha` of it.
+fn blend(below: Color, thread: Color, alpha: f32) -> Color {
+    [0, 1, 2].map(|c| below[c] + (thread[c] - below[c]) * alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::art_generator::{segments, ArtGenerator};
+    use image::{RgbImage, Rgba};
+
+    const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+    const RED: Rgb<u8> = Rgb([255, 0, 0]);
+    const BLUE: Rgb<u8> = Rgb([0, 0, 255]);
+
+    /// Red and blue threads half hiding what is below them, on a 20x20 board
+    /// that should come out purple everywhere, which only red and blue on
+    /// top of each other get close to.
+    fn purple_blender(board: &Board, background: Rgb<u8>) -> Blender {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 20, Rgb([128, 0, 128])));
+
+        Blender::new(
+            board,
+            &img,
+            &[RED, BLUE],
+            background,
+            0.5,
+            0.0,
+            ColorDistance::Rgb,
+        )
+    }
+
+    #[test]
+    fn test_threads_mix() {
+        let red = blend(to_color(&WHITE), to_color(&RED), 1.0);
+        assert_eq!(red, [1.0, 0.0, 0.0]);
+        assert_eq!(blend(red, to_color(&BLUE), 0.5), [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_mixes_towards_target() {
+        let board = Board::new(20.0, 8, 1.0).unwrap();
+
+        let mut blender = purple_blender(&board, WHITE);
+        let mut nails = blender.initial_nails();
+        assert_eq!(nails.len(), 2);
+
+        let mut colors = Vec::new();
+        while let Some((color, nail)) = blender.next_nail(&nails) {
+            nails.insert(color, nail);
+            colors.push(color);
+            if colors.len() == 200 {
+                break;
+            }
+        }
+
+        assert!(colors.contains(&RED) && colors.contains(&BLUE));
+
+        // the middle of the board is crossed by many chords of both colors
+        let middle = blender.canvas().to_rgb8().get_pixel(10, 10).0;
+        assert!(
+            middle[0] > 60 && middle[2] > 60 && middle[1] < 60,
+            "{:?}",
+            middle
+        );
+    }
+
+    #[test]
+    fn test_save_and_restore_state() {
+        let board = Board::new(20.0, 8, 1.0).unwrap();
+
+        let mut blender = purple_blender(&board, WHITE);
+        let mut nails = blender.initial_nails();
+        for _ in 0..5 {
+            let (color, nail) = blender.next_nail(&nails).unwrap();
+            nails.insert(color, nail);
+        }
+
+        let mut resumed = purple_blender(&board, WHITE);
+        resumed.restore_state(blender.save_state()).unwrap();
+        assert_eq!(resumed.canvas, blender.canvas);
+        assert_eq!(resumed.last_nails, blender.last_nails);
+    }
+
+    #[test]
+    fn test_art_matches_the_canvas() {
+        let board = std::rc::Rc::new(Board::new(20.0, 8, 1.0).unwrap());
+        let blender = || purple_blender(&board, Rgb([200, 200, 0]));
+
+        let mut generator = ArtGenerator::new(std::rc::Rc::clone(&board), Box::new(blender()));
+        for _ in 0..20 {
+            generator.step();
+        }
+
+        let mut model = blender();
+        for (color, from, to) in segments(generator.pattern()) {
+            model.lay_thread(color, from, to);
+        }
+        let canvas = model.canvas().to_rgb8();
+        let art = generator.art().to_rgba8();
+        for (x, y) in [(10, 10), (5, 12), (1, 1)] {
+            let Rgba([r, g, b, a]) = *art.get_pixel(x, y);
+            assert_eq!(a, 255);
+            for (painted, modelled) in [r, g, b].iter().zip(canvas.get_pixel(x, y).0) {
+                assert!(
+                    painted.abs_diff(modelled) <= 2,
+                    "{:?} at {}, {}",
+                    (r, g, b),
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
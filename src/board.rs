@@ -1,49 +1,416 @@
 use bresenham::Bresenham;
-use image::{imageops::FilterType, DynamicImage};
-use std::{collections::HashMap, hash::Hash};
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::{Arc, OnceLock},
+};
 
-use crate::util::Dimensions;
-
-pub(crate) type NailNailPaths = HashMap<Nail, HashMap<Nail, Vec<(u32, u32)>>>;
+use crate::{chords::Chords, outline, raster, util::Dimensions};
 
 #[derive(Debug)]
 pub struct Board {
     dimensions: Dimensions,
+    width_mm: f64,
+    height_mm: f64,
+    pixels_per_mm: f64,
     nails: Vec<Nail>,
-    paths: NailNailPaths,
+    nail_positions_mm: Vec<NailPosition>,
+    nail_indices: HashMap<Nail, usize>,
+    /// See [`Board::tangent`], `None` for nails that don't run around an edge
+    tangents: Option<Vec<(f64, f64)>>,
+    nail_diameter_mm: f64,
+    thread_width_mm: f64,
+    /// Whether the board is the circle [`Board::new`] builds
+    circle: bool,
+    /// Outline of a shaped board, moved to start at the origin
+    outline: Option<outline::Outline>,
+    mask: Option<Vec<bool>>,
+    blocked_chords: BlockedChords,
+    /// Worked out on first use, once the nail diameter and thread width are set
+    connections: OnceLock<Connections>,
+}
+
+#[derive(Debug)]
+struct Connections {
+    chords: Arc<Chords>,
+    blocked: HashSet<(Nail, Nail)>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+/// Nail position in pixels of the working image.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 pub struct Nail(pub u32, pub u32);
 
+/// Nail position in millimetres, measured from the top left corner of the board.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct NailPosition(pub f64, pub f64);
+
+/// What to do with chords that run straight across another nail. Such a thread
+/// would catch on the nail in the way, which matters once there are nails
+/// inside the frame, so they are skipped unless asked otherwise.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockedChords {
+    /// Keep every chord, threads and all
+    Allow,
+    /// Leave blocked chords out of [`Board::chords`]
+    #[default]
+    Skip,
+    /// Keep blocked chords but take this many pixels off their score
+    Penalize(i32),
+}
+
+/// Which way the thread goes around a nail, as seen from the front of the board.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Wrap {
+    Clockwise,
+    CounterClockwise,
+}
+
 impl Board {
-    // TODO use real measurements
-    pub fn new(nail_spacing_pixels: u32, nail_count: u32) -> Self {
-        assert!(nail_spacing_pixels > 0);
-        assert!(nail_count > 1);
+    /// A circular board `diameter_mm` across with `nail_count` evenly spaced
+    /// nails on its edge. The working image has `pixels_per_mm` pixels for
+    /// every millimetre of board. Like the other constructors, fails on sizes,
+    /// nail counts or resolutions that make no board, or if the resolution
+    /// puts two nails on the same pixel.
+    pub fn new(diameter_mm: f64, nail_count: u32, pixels_per_mm: f64) -> Result<Self, String> {
+        if diameter_mm <= 0.0 || diameter_mm.is_nan() {
+            return Err("board diameter must be greater than 0".to_string());
+        }
+        if nail_count < 2 {
+            return Err("a board needs at least 2 nails".to_string());
+        }
+
+        let positions = place_nails(diameter_mm, nail_count);
+
+        let mut board = Board::from_positions(
+            diameter_mm,
+            diameter_mm,
+            positions,
+            pixels_per_mm,
+            BlockedChords::Allow,
+            true,
+        )?;
+        board.circle = true;
+
+        Ok(board)
+    }
+
+    /// A rectangular board with nails evenly spread along all four sides,
+    /// starting in the top left corner and going clockwise. Every corner gets
+    /// exactly one nail, so `nail_count` must be even.
+    pub fn new_rectangle(
+        width_mm: f64,
+        height_mm: f64,
+        nail_count: u32,
+        pixels_per_mm: f64,
+    ) -> Result<Self, String> {
+        if !(width_mm > 0.0 && height_mm > 0.0) {
+            return Err("board width and height must be greater than 0".to_string());
+        }
+        if nail_count < 4 || !nail_count.is_multiple_of(2) {
+            return Err("rectangular boards need an even number of at least 4 nails".to_string());
+        }
+
+        let positions = place_nails_rectangle(width_mm, height_mm, nail_count);
+
+        Board::from_positions(
+            width_mm,
+            height_mm,
+            positions,
+            pixels_per_mm,
+            BlockedChords::Allow,
+            true,
+        )
+    }
+
+    /// A rectangular board `width_mm` wide with the same aspect ratio as `src_img`.
+    pub fn rectangle_for_image(
+        src_img: &DynamicImage,
+        width_mm: f64,
+        nail_count: u32,
+        pixels_per_mm: f64,
+    ) -> Result<Self, String> {
+        let height_mm = height_for_width(src_img.dimensions(), width_mm);
+
+        Board::new_rectangle(width_mm, height_mm, nail_count, pixels_per_mm)
+    }
+
+    /// A board cut to an arbitrary closed outline given in millimetres, with
+    /// `nail_count` nails at equal distances along it. Pixels outside the
+    /// outline are masked out, see [`Board::contains_pixel`].
+    pub fn from_outline(
+        outline_mm: &[(f64, f64)],
+        nail_count: u32,
+        pixels_per_mm: f64,
+    ) -> Result<Self, String> {
+        if outline_mm.len() < 3 {
+            return Err("outline needs at least 3 points".to_string());
+        }
+        if nail_count < 2 {
+            return Err("a board needs at least 2 nails".to_string());
+        }
+
+        let (min_x, min_y, max_x, max_y) = outline::bounds(outline_mm);
+        let outline = outline_mm
+            .iter()
+            .map(|(x, y)| (x - min_x, y - min_y))
+            .collect::<Vec<_>>();
+
+        let positions = place_nails_along(&outline, nail_count);
+
+        let mut board = Board::from_positions(
+            max_x - min_x,
+            max_y - min_y,
+            positions,
+            pixels_per_mm,
+            BlockedChords::Allow,
+            true,
+        )?;
+        board.mask = Some(outline::mask(&outline, pixels_per_mm, &board.dimensions));
+        board.outline = Some(outline);
+
+        Ok(board)
+    }
+
+    /// A board with nails wherever `nail_positions_mm` puts them, inside the
+    /// frame as well as on its edge. The board is `width_mm` by `height_mm`;
+    /// nails outside of it are an error.
+    ///
+    /// The nails can be in any order, so there is no edge for the thread to
+    /// wrap along. Chords run between nail centres whatever the nail diameter
+    /// and [`Board::wrap`] can't tell which way the thread goes.
+    pub fn from_nails(
+        width_mm: f64,
+        height_mm: f64,
+        nail_positions_mm: Vec<NailPosition>,
+        pixels_per_mm: f64,
+        blocked_chords: BlockedChords,
+    ) -> Result<Self, String> {
+        if !(width_mm > 0.0 && height_mm > 0.0) {
+            return Err("board width and height must be greater than 0".to_string());
+        }
+        if nail_positions_mm.len() < 2 {
+            return Err("a board needs at least 2 nails".to_string());
+        }
+        if let Some(NailPosition(x, y)) = nail_positions_mm.iter().find(|NailPosition(x, y)| {
+            !(0.0..=width_mm).contains(x) || !(0.0..=height_mm).contains(y)
+        }) {
+            return Err(format!(
+                "nail at ({}, {}) is off the {} by {} mm board",
+                x, y, width_mm, height_mm
+            ));
+        }
+
+        Board::from_positions(
+            width_mm,
+            height_mm,
+            nail_positions_mm,
+            pixels_per_mm,
+            blocked_chords,
+            false,
+        )
+    }
 
-        let circumference = nail_spacing_pixels * nail_count;
-        let diameter = (circumference as f64 / std::f64::consts::PI).ceil() as u32;
+    fn from_positions(
+        width_mm: f64,
+        height_mm: f64,
+        nail_positions_mm: Vec<NailPosition>,
+        pixels_per_mm: f64,
+        blocked_chords: BlockedChords,
+        around_edge: bool,
+    ) -> Result<Self, String> {
+        if pixels_per_mm <= 0.0 || pixels_per_mm.is_nan() {
+            return Err("pixels_per_mm must be greater than 0".to_string());
+        }
 
-        let nails = place_nails(diameter, nail_count);
-        let paths = precompute_paths(&nails);
+        let dimensions = Dimensions::new(
+            (width_mm * pixels_per_mm).ceil().max(1.0) as u32,
+            (height_mm * pixels_per_mm).ceil().max(1.0) as u32,
+        );
+
+        let nails = nail_positions_mm
+            .iter()
+            .map(|position| position_to_pixel(*position, pixels_per_mm, &dimensions))
+            .collect::<Vec<_>>();
+
+        let nail_indices = nails
+            .iter()
+            .enumerate()
+            .map(|(i, nail)| (*nail, i))
+            .collect::<HashMap<_, _>>();
+        if nail_indices.len() != nails.len() {
+            return Err("nails are less than a pixel apart, increase pixels_per_mm".to_string());
+        }
 
-        let dimensions: Dimensions = Dimensions::new(diameter, diameter);
+        let tangents = around_edge.then(|| edge_tangents(&nail_positions_mm));
 
-        Self {
+        Ok(Self {
             dimensions,
+            width_mm,
+            height_mm,
+            pixels_per_mm,
             nails,
-            paths,
+            nail_positions_mm,
+            nail_indices,
+            tangents,
+            nail_diameter_mm: 0.0,
+            thread_width_mm: 0.0,
+            circle: false,
+            outline: None,
+            mask: None,
+            blocked_chords,
+            connections: OnceLock::new(),
+        })
+    }
+
+    /// Give the nails a physical size. Chords then run between the sides of
+    /// the nails instead of their centres, see [`Board::attachment_point_mm`],
+    /// except on boards built with [`Board::from_nails`].
+    pub fn with_nail_diameter(mut self, nail_diameter_mm: f64) -> Result<Self, String> {
+        if nail_diameter_mm < 0.0 || !nail_diameter_mm.is_finite() {
+            return Err("nail diameter must be a number not below 0".to_string());
         }
+
+        self.nail_diameter_mm = nail_diameter_mm;
+        self.connections = OnceLock::new();
+        Ok(self)
     }
 
-    pub fn scale_image(&self, img: &DynamicImage, filter: Option<FilterType>) -> DynamicImage {
-        let filter = if filter.is_some() {
-            filter.unwrap()
+    /// Give the thread a physical width. Chords then cover every pixel the
+    /// thread runs over by how much of it the thread covers, instead of being
+    /// single pixel lines that fully cover each pixel.
+    pub fn with_thread_width(mut self, thread_width_mm: f64) -> Result<Self, String> {
+        if thread_width_mm < 0.0 || !thread_width_mm.is_finite() {
+            return Err("thread width must be a number not below 0".to_string());
+        }
+
+        self.thread_width_mm = thread_width_mm;
+        self.connections = OnceLock::new();
+        Ok(self)
+    }
+
+    fn connections(&self) -> &Connections {
+        self.connections.get_or_init(|| self.connect_nails())
+    }
+
+    fn connect_nails(&self) -> Connections {
+        let mut chords = if self.thread_width_mm > 0.0 {
+            self.paths_with_coverage()
+        } else if self.nail_diameter_mm > 0.0 && self.tangents.is_some() {
+            self.paths_between_attachments()
         } else {
-            FilterType::Lanczos3
+            precompute_paths(&self.nails, &self.dimensions)
         };
 
+        let blocked = match self.blocked_chords {
+            BlockedChords::Allow => HashSet::new(),
+            _ => find_blocked_chords(&chords),
+        };
+        if self.blocked_chords == BlockedChords::Skip {
+            for (from, to) in &blocked {
+                chords.skip(*from, *to);
+            }
+        }
+
+        Connections {
+            chords: Arc::new(chords),
+            blocked,
+        }
+    }
+
+    fn paths_between_attachments(&self) -> Chords {
+        let attachments = (0..self.nails.len())
+            .map(|i| {
+                let sides = [1.0, -1.0].map(|side| {
+                    let (x, y) = self.side_of(i, side);
+                    position_to_pixel(NailPosition(x, y), self.pixels_per_mm, &self.dimensions)
+                });
+                (sides[0], sides[1])
+            })
+            .collect::<Vec<_>>();
+
+        precompute_paths_between(&self.nails, &self.dimensions, |i, j| {
+            let pick = |(clockwise, counter), side: f64| {
+                if side > 0.0 {
+                    clockwise
+                } else {
+                    counter
+                }
+            };
+            (
+                pick(attachments[i], self.side_towards(i, j)),
+                pick(attachments[j], self.side_towards(j, i)),
+            )
+        })
+    }
+
+    fn paths_with_coverage(&self) -> Chords {
+        let width = self.thread_width_mm * self.pixels_per_mm;
+        let to_pixels = |(x, y): (f64, f64)| (x * self.pixels_per_mm, y * self.pixels_per_mm);
+
+        Chords::new(&self.nails, &self.dimensions, |i, j| {
+            let start = to_pixels(self.attachment(i, j));
+            let end = to_pixels(self.attachment(j, i));
+
+            // like the single pixel lines, leave out the pixels of the nails themselves
+            let (from, to) = (self.nails[i], self.nails[j]);
+            raster::thread_coverage(start, end, width, &self.dimensions)
+                .into_iter()
+                .filter(|(x, y, _)| Nail(*x, *y) != from && Nail(*x, *y) != to)
+                .collect()
+        })
+    }
+
+    /// Unit vector along the edge of the board at nail `i`, pointing clockwise
+    /// around it. See [`edge_tangents`].
+    fn tangent(&self, i: usize) -> (f64, f64) {
+        self.tangents
+            .as_ref()
+            .expect("nails don't run around an edge")[i]
+    }
+
+    /// `1.0` if a chord from nail `i` to nail `j` leaves `i` on its clockwise
+    /// side, `-1.0` for the counter-clockwise side.
+    fn side_towards(&self, i: usize, j: usize) -> f64 {
+        let (tx, ty) = self.tangent(i);
+        let NailPosition(xi, yi) = self.nail_positions_mm[i];
+        let NailPosition(xj, yj) = self.nail_positions_mm[j];
+
+        if tx * (xj - xi) + ty * (yj - yi) >= 0.0 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    fn side_of(&self, i: usize, side: f64) -> (f64, f64) {
+        let (tx, ty) = self.tangent(i);
+        let NailPosition(x, y) = self.nail_positions_mm[i];
+        let radius = self.nail_diameter_mm / 2.0;
+
+        (x + side * radius * tx, y + side * radius * ty)
+    }
+
+    /// Where a chord from nail `i` to nail `j` leaves `i`, its centre unless
+    /// the nails run around an edge and have a size.
+    fn attachment(&self, i: usize, j: usize) -> (f64, f64) {
+        match self.tangents {
+            Some(_) if self.nail_diameter_mm > 0.0 => self.side_of(i, self.side_towards(i, j)),
+            _ => {
+                let NailPosition(x, y) = self.nail_positions_mm[i];
+                (x, y)
+            }
+        }
+    }
+
+    /// Resize `img` so it covers the whole board, cropping whatever sticks out
+    /// when the aspect ratios differ.
+    pub fn scale_image(&self, img: &DynamicImage, filter: Option<FilterType>) -> DynamicImage {
+        let filter = filter.unwrap_or(FilterType::Lanczos3);
+
         img.resize_to_fill(self.dimensions.width(), self.dimensions.height(), filter)
     }
 
@@ -51,137 +418,579 @@ impl Board {
         &self.dimensions
     }
 
+    pub fn width_mm(&self) -> f64 {
+        self.width_mm
+    }
+
+    pub fn height_mm(&self) -> f64 {
+        self.height_mm
+    }
+
+    pub fn pixels_per_mm(&self) -> f64 {
+        self.pixels_per_mm
+    }
+
     pub fn nails(&self) -> &Vec<Nail> {
         &self.nails
     }
 
-    pub fn paths(&self) -> &NailNailPaths {
-        &self.paths
+    pub fn nail_positions_mm(&self) -> &Vec<NailPosition> {
+        &self.nail_positions_mm
+    }
+
+    /// Index of the nail in [`Board::nails`], counting from the first nail placed.
+    pub fn nail_index(&self, nail: Nail) -> Option<usize> {
+        self.nail_indices.get(&nail).copied()
+    }
+
+    pub fn nail_position_mm(&self, nail: Nail) -> Option<NailPosition> {
+        self.nail_index(nail).map(|i| self.nail_positions_mm[i])
+    }
+
+    pub fn nail_diameter_mm(&self) -> f64 {
+        self.nail_diameter_mm
+    }
+
+    /// Width of the thread, `0.0` when chords are single pixel lines.
+    pub fn thread_width_mm(&self) -> f64 {
+        self.thread_width_mm
+    }
+
+    /// Where a thread running from `nail` towards `towards` touches `nail`.
+    ///
+    /// Every nail has two attachment points, one on either side along the edge
+    /// of the board. Thread wraps around the outside of a nail, so a chord
+    /// always leaves from the side facing the nail it runs to. Nails of a
+    /// [`Board::from_nails`] board only have their centre.
+    pub fn attachment_point_mm(&self, nail: Nail, towards: Nail) -> NailPosition {
+        let i = self.nail_index(nail).expect("nail is not on this board");
+        let j = self.nail_index(towards).expect("nail is not on this board");
+
+        let (x, y) = self.attachment(i, j);
+        NailPosition(x, y)
+    }
+
+    /// Which way the thread wraps around `to` after running to it from `from`.
+    /// The thread catches the side of `to` facing `from` and keeps turning the
+    /// same way around the outside of the nail. `None` on a
+    /// [`Board::from_nails`] board, where nails have no outside.
+    pub fn wrap(&self, from: Nail, to: Nail) -> Option<Wrap> {
+        let i = self.nail_index(from).expect("nail is not on this board");
+        let j = self.nail_index(to).expect("nail is not on this board");
+        self.tangents.as_ref()?;
+
+        let side = self.side_towards(j, i);
+        let (tx, ty) = self.tangent(j);
+        let NailPosition(xi, yi) = self.nail_positions_mm[i];
+        let NailPosition(xj, yj) = self.nail_positions_mm[j];
+
+        // y points down, so a positive cross product turns clockwise
+        let cross = side * (tx * (yj - yi) - ty * (xj - xi));
+        if cross >= 0.0 {
+            Some(Wrap::Clockwise)
+        } else {
+            Some(Wrap::CounterClockwise)
+        }
+    }
+
+    /// Straight line distance between two nail centres in millimetres.
+    pub fn distance_mm(&self, from: Nail, to: Nail) -> f64 {
+        let from = self
+            .nail_position_mm(from)
+            .expect("nail is not on this board");
+        let to = self
+            .nail_position_mm(to)
+            .expect("nail is not on this board");
+
+        (to.0 - from.0).hypot(to.1 - from.1)
+    }
+
+    /// Whether the pixel lies on the board. Pixels outside a shaped board never
+    /// get scored.
+    pub fn contains_pixel(&self, x: u32, y: u32) -> bool {
+        match &self.mask {
+            Some(mask) => mask[(y * self.dimensions.width() + x) as usize],
+            None => true,
+        }
+    }
+
+    /// Whether the board is round, filling the circle its image is a square
+    /// around.
+    pub fn is_circle(&self) -> bool {
+        self.circle
+    }
+
+    /// Outline of a shaped board in millimetres from the top left corner of the
+    /// board, `None` if the board covers the whole image.
+    pub fn outline_mm(&self) -> Option<&[(f64, f64)]> {
+        self.outline.as_deref()
+    }
+
+    /// Row major mask of the pixels on the board, `None` if the board covers
+    /// the whole image.
+    pub fn mask(&self) -> Option<&Vec<bool>> {
+        self.mask.as_ref()
+    }
+
+    /// Score penalty for the chord between two nails, non-zero only for chords
+    /// running across another nail when the board penalizes those.
+    pub fn chord_penalty(&self, from: Nail, to: Nail) -> i32 {
+        match self.blocked_chords {
+            BlockedChords::Penalize(penalty)
+                if self.connections().blocked.contains(&(from, to)) =>
+            {
+                penalty
+            }
+            _ => 0,
+        }
+    }
+
+    /// Pixels covered by the chord between every pair of nails. Shared rather
+    /// than copied by whatever needs them.
+    pub fn chords(&self) -> &Arc<Chords> {
+        &self.connections().chords
+    }
+
+    /// [`Board::chords`] without the pixels that lie off a shaped board.
+    pub fn chords_on_board(&self) -> Arc<Chords> {
+        match self.mask {
+            Some(_) => Arc::new(self.chords().retain(|x, y| self.contains_pixel(x, y))),
+            None => Arc::clone(self.chords()),
+        }
     }
 }
 
-fn place_nails(diameter: u32, nail_count: u32) -> Vec<Nail> {
-    let radius = (diameter - 1) as f64 / 2.0;
-    let origin = radius + 0.5;
+fn place_nails(diameter_mm: f64, nail_count: u32) -> Vec<NailPosition> {
+    let radius = diameter_mm / 2.0;
     let rad_spacing = 2.0 * std::f64::consts::PI / nail_count as f64;
 
-    let nails = (0..nail_count)
+    (0..nail_count)
         .map(|i| {
-            let x = (origin + (radius) * (rad_spacing * i as f64).cos()).floor() as u32;
-            let y = (origin + (radius) * (rad_spacing * i as f64).sin()).floor() as u32;
-            Nail(x, y)
+            let x = radius + radius * (rad_spacing * i as f64).cos();
+            let y = radius + radius * (rad_spacing * i as f64).sin();
+            NailPosition(x, y)
         })
+        .collect::<Vec<_>>()
+}
+
+fn place_nails_rectangle(width_mm: f64, height_mm: f64, nail_count: u32) -> Vec<NailPosition> {
+    let half_count = nail_count / 2;
+    let width_ratio = width_mm / (width_mm + height_mm);
+    let nails_wide = ((half_count as f64 * width_ratio).round() as u32).clamp(1, half_count - 1);
+    let nails_tall = half_count - nails_wide;
+
+    let x_spacing = width_mm / nails_wide as f64;
+    let y_spacing = height_mm / nails_tall as f64;
+
+    let top = (0..nails_wide).map(|i| NailPosition(i as f64 * x_spacing, 0.0));
+    let right = (0..nails_tall).map(|i| NailPosition(width_mm, i as f64 * y_spacing));
+    let bottom = (0..nails_wide).map(|i| NailPosition(width_mm - i as f64 * x_spacing, height_mm));
+    let left = (0..nails_tall).map(|i| NailPosition(0.0, height_mm - i as f64 * y_spacing));
+
+    top.chain(right).chain(bottom).chain(left).collect()
+}
+
+fn place_nails_along(outline: &[(f64, f64)], nail_count: u32) -> Vec<NailPosition> {
+    let edges = (0..outline.len())
+        .map(|i| (outline[i], outline[(i + 1) % outline.len()]))
         .collect::<Vec<_>>();
+    let perimeter: f64 = edges
+        .iter()
+        .map(|((x1, y1), (x2, y2))| (x2 - x1).hypot(y2 - y1))
+        .sum();
+    let spacing = perimeter / nail_count as f64;
+
+    let mut nails = Vec::with_capacity(nail_count as usize);
+    let mut edge_start = 0.0;
+
+    for ((x1, y1), (x2, y2)) in edges {
+        let length = (x2 - x1).hypot(y2 - y1);
+
+        while nails.len() < nail_count as usize {
+            let along = nails.len() as f64 * spacing - edge_start;
+            if along >= length {
+                break;
+            }
+            let t = along / length;
+            nails.push(NailPosition(x1 + (x2 - x1) * t, y1 + (y2 - y1) * t));
+        }
+
+        edge_start += length;
+    }
 
     nails
 }
 
+/// For nails placed in order around the edge of a board, the direction of the
+/// edge at every nail, taken from the nails either side of it. That follows
+/// the straight sides of rectangles and outlines as well as circles. Tangents
+/// point clockwise around the board whichever way the nails go round.
+fn edge_tangents(positions: &[NailPosition]) -> Vec<(f64, f64)> {
+    let n = positions.len();
+
+    // twice the signed area, y points down so clockwise is positive
+    let area: f64 = (0..n)
+        .map(|i| {
+            let (NailPosition(x1, y1), NailPosition(x2, y2)) =
+                (positions[i], positions[(i + 1) % n]);
+            x1 * y2 - x2 * y1
+        })
+        .sum();
+    let orientation = if area < 0.0 { -1.0 } else { 1.0 };
+
+    (0..n)
+        .map(|i| {
+            let NailPosition(x, y) = positions[i];
+            let NailPosition(prev_x, prev_y) = positions[(i + n - 1) % n];
+            let NailPosition(next_x, next_y) = positions[(i + 1) % n];
+
+            let (mut dx, mut dy) = (next_x - prev_x, next_y - prev_y);
+            if dx.hypot(dy) < f64::EPSILON {
+                // only two nails, the edge runs across the line between them
+                (dx, dy) = (y - next_y, next_x - x);
+            }
+            let length = dx.hypot(dy);
+
+            (orientation * dx / length, orientation * dy / length)
+        })
+        .collect()
+}
+
+fn height_for_width((width, height): (u32, u32), width_mm: f64) -> f64 {
+    width_mm * height as f64 / width as f64
+}
+
+fn position_to_pixel(position: NailPosition, pixels_per_mm: f64, dimensions: &Dimensions) -> Nail {
+    let x = (position.0 * pixels_per_mm).floor().max(0.0) as u32;
+    let y = (position.1 * pixels_per_mm).floor().max(0.0) as u32;
+
+    Nail(
+        x.min(dimensions.width() - 1),
+        y.min(dimensions.height() - 1),
+    )
+}
+
+fn precompute_paths(nails: &[Nail], dimensions: &Dimensions) -> Chords {
+    precompute_paths_between(nails, dimensions, |i, j| (nails[i], nails[j]))
+}
+
+/// Single pixel paths for every pair of nails, keyed by nail centre. `endpoints`
+/// gives the pixels a chord between nails `i` and `j` actually runs between.
+fn precompute_paths_between<F>(nails: &[Nail], dimensions: &Dimensions, endpoints: F) -> Chords
+where
+    F: Fn(usize, usize) -> (Nail, Nail) + Sync,
+{
+    Chords::new(nails, dimensions, |i, j| {
+        let (start, end) = endpoints(i, j);
+
+        let bresenham = Bresenham::new(
+            (start.0 as isize, start.1 as isize),
+            (end.0 as isize, end.1 as isize),
+        );
+
+        // skip the start point. Bresenham skips the end point automatically
+        bresenham
+            .map(|t| (t.0 as u32, t.1 as u32, 1.0))
+            .skip(1)
+            .collect()
+    })
+}
+
+/// Chords whose pixels run over another nail, in both directions.
+fn find_blocked_chords(chords: &Chords) -> HashSet<(Nail, Nail)> {
+    let nail_set = chords.nails().iter().copied().collect::<HashSet<_>>();
+
+    chords
+        .nails()
+        .iter()
+        .flat_map(|from| {
+            chords
+                .from(*from)
+                .filter(|(_, chord)| {
+                    chord
+                        .coverage()
+                        .any(|(x, y, _)| nail_set.contains(&Nail(x, y)))
+                })
+                .map(move |(to, _)| (*from, to))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::raster::Coverage;
+
+    fn pixels(chords: &Chords, from: Nail, to: Nail) -> Vec<Coverage> {
+        chords.get(from, to).unwrap().coverage().collect()
+    }
 
     #[test]
     fn test_precompute_paths() {
         let nails = vec![Nail(0, 0), Nail(0, 2), Nail(2, 0), Nail(2, 2)];
 
-        let paths = precompute_paths(&nails);
+        let chords = precompute_paths(&nails, &Dimensions::new(3, 3));
+        let paths = |from, to| pixels(&chords, from, to);
 
         // Check the forward paths
-        assert_eq!(paths[&Nail(0, 0)][&Nail(0, 2)], vec![(0, 1)]);
-        assert_eq!(paths[&Nail(0, 0)][&Nail(2, 0)], vec![(1, 0)]);
-        assert_eq!(paths[&Nail(0, 0)][&Nail(2, 2)], vec![(1, 1)]);
-        assert_eq!(paths[&Nail(0, 2)][&Nail(2, 0)], vec![(1, 1)]);
-        assert_eq!(paths[&Nail(0, 2)][&Nail(2, 2)], vec![(1, 2)]);
-        assert_eq!(paths[&Nail(2, 0)][&Nail(2, 2)], vec![(2, 1)]);
+        assert_eq!(paths(Nail(0, 0), Nail(0, 2)), vec![(0, 1, 1.0)]);
+        assert_eq!(paths(Nail(0, 0), Nail(2, 0)), vec![(1, 0, 1.0)]);
+        assert_eq!(paths(Nail(0, 0), Nail(2, 2)), vec![(1, 1, 1.0)]);
+        assert_eq!(paths(Nail(0, 2), Nail(2, 0)), vec![(1, 1, 1.0)]);
+        assert_eq!(paths(Nail(0, 2), Nail(2, 2)), vec![(1, 2, 1.0)]);
+        assert_eq!(paths(Nail(2, 0), Nail(2, 2)), vec![(2, 1, 1.0)]);
 
         // Check the reverse paths
-        assert_eq!(paths[&Nail(0, 2)][&Nail(0, 0)], vec![(0, 1)]);
-        assert_eq!(paths[&Nail(2, 0)][&Nail(0, 0)], vec![(1, 0)]);
-        assert_eq!(paths[&Nail(2, 2)][&Nail(0, 0)], vec![(1, 1)]);
-        assert_eq!(paths[&Nail(2, 0)][&Nail(0, 2)], vec![(1, 1)]);
-        assert_eq!(paths[&Nail(2, 2)][&Nail(0, 2)], vec![(1, 2)]);
-        assert_eq!(paths[&Nail(2, 2)][&Nail(2, 0)], vec![(2, 1)]);
+        assert_eq!(paths(Nail(0, 2), Nail(0, 0)), vec![(0, 1, 1.0)]);
+        assert_eq!(paths(Nail(2, 0), Nail(0, 0)), vec![(1, 0, 1.0)]);
+        assert_eq!(paths(Nail(2, 2), Nail(0, 0)), vec![(1, 1, 1.0)]);
+        assert_eq!(paths(Nail(2, 0), Nail(0, 2)), vec![(1, 1, 1.0)]);
+        assert_eq!(paths(Nail(2, 2), Nail(0, 2)), vec![(1, 2, 1.0)]);
+        assert_eq!(paths(Nail(2, 2), Nail(2, 0)), vec![(2, 1, 1.0)]);
     }
-}
 
-fn precompute_paths(nails: &[Nail]) -> NailNailPaths {
-    // TODO account for nail size
-    let mut nail_nail_paths = HashMap::new();
+    #[test]
+    fn test_real_measurements() {
+        let board = Board::new(100.0, 4, 2.0).unwrap();
 
-    for i in 0..nails.len() {
-        for j in (i + 1)..nails.len() {
-            let start = nails[i];
-            let end = nails[j];
+        assert_eq!(*board.dimensions(), Dimensions::new(200, 200));
 
-            let bresenham = Bresenham::new(
-                (start.0 as isize, start.1 as isize),
-                (end.0 as isize, end.1 as isize),
-            );
+        let positions = board.nail_positions_mm();
+        assert!((positions[0].0 - 100.0).abs() < 1e-9);
+        assert!((positions[0].1 - 50.0).abs() < 1e-9);
+        assert!((positions[1].0 - 50.0).abs() < 1e-9);
+        assert!((positions[1].1 - 100.0).abs() < 1e-9);
 
-            // skip the start point. Bresenham skips the end point automatically
-            let path = bresenham
-                .map(|t| (t.0 as u32, t.1 as u32))
-                .into_iter()
-                .skip(1)
-                .collect::<Vec<_>>();
+        assert_eq!(board.nails()[0], Nail(199, 100));
+        assert_eq!(board.nails()[2], Nail(0, 100));
 
-            let forward_paths = nail_nail_paths.entry(start).or_insert(HashMap::new());
-            forward_paths.insert(end, path.clone());
+        let across = board.distance_mm(board.nails()[0], board.nails()[2]);
+        assert!((across - 100.0).abs() < 1e-9);
+    }
 
-            let reverse_paths = nail_nail_paths.entry(end).or_insert(HashMap::new());
-            reverse_paths.insert(start, path.clone());
+    #[test]
+    fn test_rectangle_nails() {
+        let board = Board::new_rectangle(100.0, 50.0, 12, 1.0).unwrap();
+        let positions = board.nail_positions_mm();
+
+        assert_eq!(positions.len(), 12);
+        assert_eq!(*board.dimensions(), Dimensions::new(100, 50));
+
+        // 4 nails on the long sides, 2 on the short ones, 25mm apart everywhere
+        assert_eq!(positions[0], NailPosition(0.0, 0.0));
+        assert_eq!(positions[3], NailPosition(75.0, 0.0));
+        assert_eq!(positions[4], NailPosition(100.0, 0.0));
+        assert_eq!(positions[6], NailPosition(100.0, 50.0));
+        assert_eq!(positions[10], NailPosition(0.0, 50.0));
+        assert_eq!(positions[11], NailPosition(0.0, 25.0));
+
+        // each corner appears exactly once
+        for corner in [(0.0, 0.0), (100.0, 0.0), (100.0, 50.0), (0.0, 50.0)] {
+            let count = positions.iter().filter(|p| (p.0, p.1) == corner).count();
+            assert_eq!(count, 1);
         }
+
+        // pixel nails stay inside the image
+        assert!(board.nails().iter().all(|n| n.0 < 100 && n.1 < 50));
     }
 
-    nail_nail_paths
-}
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(Board::new(0.0, 8, 1.0).is_err());
+        assert!(Board::new(20.0, 1, 1.0).is_err());
+        assert!(Board::new(20.0, 8, f64::NAN).is_err());
+        assert!(Board::new_rectangle(20.0, 20.0, 6, -1.0).is_err());
+        assert!(Board::new_rectangle(20.0, 20.0, 7, 1.0).is_err());
+        assert!(Board::new_rectangle(20.0, 0.0, 8, 1.0).is_err());
+        assert!(Board::from_outline(&[(0.0, 0.0), (10.0, 0.0)], 8, 1.0).is_err());
+        let nails = vec![NailPosition(0.0, 0.0)];
+        assert!(Board::from_nails(10.0, 10.0, nails, 1.0, BlockedChords::Skip).is_err());
+
+        let board = || Board::new(20.0, 8, 1.0).unwrap();
+        assert!(board().with_nail_diameter(-1.0).is_err());
+        assert!(board().with_nail_diameter(f64::NAN).is_err());
+        assert!(board().with_thread_width(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_height_for_width() {
+        assert_eq!(height_for_width((400, 200), 600.0), 300.0);
+        assert_eq!(height_for_width((200, 400), 300.0), 600.0);
+        assert_eq!(height_for_width((300, 300), 600.0), 600.0);
+    }
+
+    #[test]
+    fn test_outline_board() {
+        // a 40mm square given away from the origin, 8 nails 20mm apart
+        let outline = vec![(10.0, 10.0), (50.0, 10.0), (50.0, 50.0), (10.0, 50.0)];
+        let board = Board::from_outline(&outline, 8, 1.0).unwrap();
+        let positions = board.nail_positions_mm();
+
+        assert_eq!(*board.dimensions(), Dimensions::new(40, 40));
+        assert_eq!(positions.len(), 8);
+        assert_eq!(positions[0], NailPosition(0.0, 0.0));
+        assert_eq!(positions[1], NailPosition(20.0, 0.0));
+        assert_eq!(positions[2], NailPosition(40.0, 0.0));
+        assert_eq!(positions[5], NailPosition(20.0, 40.0));
+    }
 
-// // not used yet
-// fn place_nails_rectangle(width: u32, height: u32, nail_spacing_pixels: u32) -> Vec<(u32, u32)> {
-//     let mut nails: Vec<(u32, u32)> = Vec::new();
-
-//     for x in (0..=width - nail_spacing_pixels).step_by(nail_spacing_pixels as usize) {
-//         nails.push((x, 0));
-//     }
-//     for y in (0..=height - nail_spacing_pixels).step_by(nail_spacing_pixels as usize) {
-//         nails.push((width, y));
-//     }
-//     for x in (0..=width - nail_spacing_pixels)
-//         .rev()
-//         .step_by(nail_spacing_pixels as usize)
-//     {
-//         nails.push((x, height));
-//     }
-//     for y in (0..=height - nail_spacing_pixels)
-//         .rev()
-//         .step_by(nail_spacing_pixels as usize)
-//     {
-//         nails.push((0, y));
-//     }
-//     nails
-// }
-// // not used yet
-// fn new_rectangle(
-//     src_img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
-//     nail_spacing_pixels: u32,
-//     nail_count: u32,
-// ) -> Self {
-//     assert!(nail_spacing_pixels > 0);
-//     assert!(nail_count > 1);
-//     assert!(nail_count % 2 == 0);
-
-//     let width_ratio = src_img.width() as f64 / (src_img.width() + src_img.height()) as f64;
-//     let nails_wide = (nail_count as f64 * width_ratio / 2.0).ceil() as u32;
-//     let nails_tall = (nail_count - nails_wide * 2) / 2;
-
-//     let width = nails_wide * nail_spacing_pixels;
-//     let height = nails_tall * nail_spacing_pixels;
-
-//     let nails = place_nails_rectangle(width, height, nail_spacing_pixels);
-
-//     Self {
-//         width,
-//         height,
-//         nails,
-//     }
-// }
+    #[test]
+    fn test_outline_mask() {
+        let triangle = vec![(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)];
+        let board = Board::from_outline(&triangle, 6, 1.0).unwrap();
+
+        assert!(board.contains_pixel(1, 1));
+        assert!(board.contains_pixel(7, 1));
+        assert!(!board.contains_pixel(8, 8));
+        assert!(!board.contains_pixel(9, 1));
+    }
+
+    #[test]
+    fn test_blocked_chords() {
+        // three nails in a row and one off to the side
+        let positions = vec![
+            NailPosition(0.5, 0.5),
+            NailPosition(2.5, 0.5),
+            NailPosition(4.5, 0.5),
+            NailPosition(2.5, 4.5),
+        ];
+        let (left, middle, right) = (Nail(0, 0), Nail(2, 0), Nail(4, 0));
+
+        let board =
+            Board::from_nails(5.0, 5.0, positions.clone(), 1.0, BlockedChords::Skip).unwrap();
+        assert!(board.chords().get(left, right).is_none());
+        assert!(board.chords().get(right, left).is_none());
+        assert!(board.chords().get(left, middle).is_some());
+        assert_eq!(board.chords().from(left).count(), 2);
+
+        let board =
+            Board::from_nails(5.0, 5.0, positions, 1.0, BlockedChords::Penalize(7)).unwrap();
+        assert!(board.chords().get(left, right).is_some());
+        assert_eq!(board.chord_penalty(left, right), 7);
+        assert_eq!(board.chord_penalty(right, left), 7);
+        assert_eq!(board.chord_penalty(left, middle), 0);
+    }
+
+    #[test]
+    fn test_nails_off_the_board() {
+        let inside = vec![NailPosition(0.0, 0.0), NailPosition(5.0, 5.0)];
+        assert!(Board::from_nails(5.0, 5.0, inside, 1.0, BlockedChords::Skip).is_ok());
+
+        for outside in [NailPosition(6.0, 2.0), NailPosition(2.0, -0.5)] {
+            let positions = vec![NailPosition(0.0, 0.0), outside];
+            let err = Board::from_nails(5.0, 5.0, positions, 1.0, BlockedChords::Skip).unwrap_err();
+            assert!(err.contains("off the"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_thread_width() {
+        let board = Board::new_rectangle(20.0, 20.0, 4, 1.0)
+            .unwrap()
+            .with_thread_width(0.5)
+            .unwrap();
+        let [top_left, top_right, ..] = [0, 1, 2, 3].map(|i| board.nails()[i]);
+
+        assert_eq!(board.thread_width_mm(), 0.5);
+
+        // the top edge runs along the border of the image, so only the half of
+        // the thread inside it covers anything
+        let path = pixels(board.chords(), top_left, top_right);
+        assert_eq!(path.len(), 18);
+        assert!(path
+            .iter()
+            .all(|(_, y, coverage)| *y == 0 && *coverage == 0.25));
+    }
+
+    #[test]
+    fn test_attachment_points() {
+        let board = Board::new(100.0, 4, 1.0)
+            .unwrap()
+            .with_nail_diameter(10.0)
+            .unwrap();
+        let [right, bottom, _left, top] = [0, 1, 2, 3].map(|i| board.nails()[i]);
+
+        assert_eq!(board.nail_diameter_mm(), 10.0);
+        assert_eq!(
+            board.attachment_point_mm(right, bottom),
+            NailPosition(100.0, 55.0)
+        );
+        assert_eq!(
+            board.attachment_point_mm(right, top),
+            NailPosition(100.0, 45.0)
+        );
+        assert_eq!(
+            board.attachment_point_mm(bottom, right),
+            NailPosition(55.0, 100.0)
+        );
+
+        // the chord now runs between the sides of the nails
+        let path = pixels(board.chords(), right, bottom);
+        assert_eq!(path.first(), Some(&(98, 56, 1.0)));
+        assert_eq!(path, pixels(board.chords(), bottom, right));
+    }
+
+    #[test]
+    fn test_wrap() {
+        let board = Board::new(100.0, 4, 1.0).unwrap();
+        let [right, bottom, left, _top] = [0, 1, 2, 3].map(|i| board.nails()[i]);
+
+        // arriving at the bottom nail from the right, the thread catches its
+        // right side and goes round underneath it
+        assert_eq!(board.wrap(right, bottom), Some(Wrap::Clockwise));
+        assert_eq!(board.wrap(left, bottom), Some(Wrap::CounterClockwise));
+        assert_eq!(board.wrap(bottom, right), Some(Wrap::CounterClockwise));
+    }
+
+    #[test]
+    fn test_attachment_points_along_straight_edges() {
+        let board = Board::new_rectangle(100.0, 50.0, 12, 1.0)
+            .unwrap()
+            .with_nail_diameter(10.0)
+            .unwrap();
+        let nail = |i: usize| board.nails()[i];
+
+        // the nail at 25mm along the top edge, whose sides are left and right
+        // of it however far it is from the centre
+        assert_eq!(
+            board.attachment_point_mm(nail(1), nail(6)),
+            NailPosition(30.0, 0.0)
+        );
+        assert_eq!(
+            board.attachment_point_mm(nail(1), nail(10)),
+            NailPosition(20.0, 0.0)
+        );
+        assert_eq!(board.wrap(nail(6), nail(1)), Some(Wrap::CounterClockwise));
+
+        // the same square given counter-clockwise
+        let outline = vec![(0.0, 0.0), (0.0, 40.0), (40.0, 40.0), (40.0, 0.0)];
+        let board = Board::from_outline(&outline, 8, 1.0)
+            .unwrap()
+            .with_nail_diameter(4.0)
+            .unwrap();
+        assert_eq!(
+            board.attachment_point_mm(board.nails()[1], board.nails()[4]),
+            NailPosition(0.0, 22.0)
+        );
+    }
+
+    #[test]
+    fn test_free_nails_attach_at_their_centre() {
+        let positions = vec![
+            NailPosition(0.0, 0.0),
+            NailPosition(10.0, 10.0),
+            NailPosition(20.0, 0.0),
+        ];
+        let board = Board::from_nails(20.0, 20.0, positions, 1.0, BlockedChords::Allow)
+            .unwrap()
+            .with_nail_diameter(4.0)
+            .unwrap();
+        let [a, centre, _] = [0, 1, 2].map(|i| board.nails()[i]);
+
+        assert_eq!(
+            board.attachment_point_mm(centre, a),
+            NailPosition(10.0, 10.0)
+        );
+        assert_eq!(board.wrap(a, centre), None);
+    }
+}
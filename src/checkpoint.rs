@@ -0,0 +1,189 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    art_algo::StrandPositions,
+    art_generator::{sort_by_palette, ArtGenerator, NailPattern},
+    board::Nail,
+    config::Config,
+    util::{parse_hex_color, to_hex_color},
+};
+
+/// A run stopped between two steps, saved as JSON so a crashed or cancelled
+/// run can carry on where it was rather than start over.
+///
+/// The board and the algorithm are rebuilt from `config`, then the algorithm
+/// is restored from `algo_state` and the art redrawn from `pattern`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The job being run. Its thread colors should be the palette the run
+    /// actually uses, k-means doesn't extract the same one every time.
+    pub config: Config,
+    pub step: usize,
+    #[serde(with = "hex_colors")]
+    pub current_nails: Vec<(image::Rgb<u8>, Nail)>,
+    #[serde(with = "hex_colors")]
+    pub pattern: NailPattern,
+    /// Whatever [`crate::ArtAlgo::save_state`] returned
+    pub algo_state: serde_json::Value,
+    /// Frames recorded so far by the job's animation, if it has one, see
+    /// [`crate::export::BuildAnimation::resume`]
+    #[serde(default)]
+    pub animation_frames: usize,
+}
+
+impl Checkpoint {
+    pub fn new(config: &Config, step: usize, generator: &ArtGenerator) -> Self {
+        let mut current_nails = generator
+            .current_nails()
+            .iter()
+            .map(|(color, nail)| (*color, *nail))
+            .collect::<Vec<_>>();
+        sort_by_palette(&mut current_nails, &generator.palette());
+
+        Self {
+            config: config.clone(),
+            step,
+            current_nails,
+            pattern: generator.pattern().clone(),
+            algo_state: generator.algo_state(),
+            animation_frames: 0,
+        }
+    }
+
+    pub fn with_animation_frames(mut self, animation_frames: usize) -> Self {
+        self.animation_frames = animation_frames;
+        self
+    }
+
+    pub fn current_nails(&self) -> StrandPositions {
+        self.current_nails.iter().copied().collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let checkpoint: Checkpoint = serde_json::from_str(&text)?;
+        checkpoint.config.validate()?;
+
+        Ok(checkpoint)
+    }
+
+    /// Save to `path` by way of a temporary file next to it, so a run killed
+    /// while saving still leaves the previous checkpoint behind.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+
+        std::fs::write(&temp_path, serde_json::to_string(self)?)?;
+        std::fs::rename(&temp_path, path)?;
+
+        Ok(())
+    }
+}
+
+/// `(color, nail)` pairs with the color as hex.
+mod hex_colors {
+    use super::*;
+    use image::Rgb;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        nails: &[(Rgb<u8>, Nail)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        nails
+            .iter()
+            .map(|(color, nail)| (to_hex_color(color), *nail))
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(Rgb<u8>, Nail)>, D::Error> {
+        let nails: Vec<(String, Nail)> = Vec::deserialize(deserializer)?;
+
+        nails
+            .into_iter()
+            .map(|(color, nail)| {
+                parse_hex_color(&color)
+                    .map(|color| (color, nail))
+                    .map_err(serde::de::Error::custom)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{ArtAlgo, Board, Grayscale};
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    #[test]
+    fn test_resume_matches_uninterrupted_run() {
+        let config = Config::from_toml(
+            r#"
+            input = "gradient.png"
+
+            [board]
+            nail_count = 16
+            diameter_mm = 20.0
+            pixels_per_mm = 1.0
+
+            [threads]
+            colors = ["000000"]
+
+            [algorithm]
+            kind = "grayscale"
+            line_opacity = 0.5
+            "#,
+        )
+        .unwrap();
+
+        let board = Rc::new(Board::new(20.0, 16, 1.0).unwrap());
+        // darker towards the bottom right, lopsided so no two chords tie
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(20, 20, |x, y| {
+            Rgb([255 - (x * 9 + y * 4) as u8; 3])
+        }));
+        let algo = || Box::new(Grayscale::new(&board, &img, Rgb([0, 0, 0]), 0.5, 0.0));
+
+        let mut generator = ArtGenerator::new(Rc::clone(&board), algo());
+        for _ in 0..3 {
+            generator.step().unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("stringify-checkpoint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.json");
+        Checkpoint::new(&config, 3, &generator)
+            .with_animation_frames(2)
+            .save(&path)
+            .unwrap();
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(checkpoint.step, 3);
+        assert_eq!(checkpoint.animation_frames, 2);
+        assert_eq!(checkpoint.config, config);
+
+        let mut resumed_algo = algo();
+        resumed_algo
+            .restore_state(checkpoint.algo_state.clone())
+            .unwrap();
+        let mut resumed = ArtGenerator::resume(
+            Rc::clone(&board),
+            resumed_algo,
+            checkpoint.current_nails(),
+            checkpoint.pattern.clone(),
+        );
+
+        assert_eq!(resumed.pattern(), generator.pattern());
+        assert_eq!(resumed.art().as_bytes(), generator.art().as_bytes());
+        assert_eq!(resumed.step(), generator.step());
+    }
+}
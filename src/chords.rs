@@ -0,0 +1,317 @@
+use std::{collections::HashMap, fmt};
+
+use rayon::prelude::*;
+
+use crate::{board::Nail, raster::Coverage, util::Dimensions};
+
+/// The pixels covered by the chord between every pair of nails.
+///
+/// Each chord is stored once for both directions, as row major pixel indices
+/// packed one after the other, so a board with 500 nails takes a few bytes per
+/// pixel of thread rather than a map per nail. Coverage is only stored when
+/// some chord covers part of a pixel.
+pub struct Chords {
+    nails: Vec<Nail>,
+    indices: HashMap<Nail, usize>,
+    width: u32,
+    /// Where the pixels of every pair start, see [`Chords::pair_of`], followed by
+    /// the end of the last pair
+    offsets: Vec<usize>,
+    pixels: Vec<u32>,
+    coverage: Option<Vec<f32>>,
+    skipped: Vec<bool>,
+}
+
+/// The pixels of one chord, see [`Chords::get`].
+#[derive(Debug, Clone, Copy)]
+pub struct Chord<'a> {
+    width: u32,
+    pixels: &'a [u32],
+    coverage: Option<&'a [f32]>,
+}
+
+impl Chords {
+    /// Every chord between `nails`, with `chord(i, j)` giving the pixels the
+    /// one between nails `i < j` covers. Chords are worked out in parallel.
+    pub fn new<F>(nails: &[Nail], dimensions: &Dimensions, chord: F) -> Self
+    where
+        F: Fn(usize, usize) -> Vec<Coverage> + Sync,
+    {
+        let width = dimensions.width();
+
+        // one row of pairs per nail, packed as soon as it is worked out
+        let rows = (0..nails.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut lengths = Vec::with_capacity(nails.len() - i - 1);
+                let mut pixels = Vec::new();
+                let mut coverage = Vec::new();
+
+                for j in i + 1..nails.len() {
+                    let path = chord(i, j);
+                    lengths.push(path.len());
+                    for (x, y, c) in path {
+                        pixels.push(y * width + x);
+                        coverage.push(c);
+                    }
+                }
+
+                (lengths, pixels, coverage)
+            })
+            .collect::<Vec<_>>();
+
+        let pair_count = nails.len() * nails.len().saturating_sub(1) / 2;
+        let pixel_count = rows.iter().map(|(_, pixels, _)| pixels.len()).sum();
+        let partial = rows
+            .iter()
+            .any(|(_, _, coverage)| coverage.iter().any(|c| *c != 1.0));
+
+        let mut offsets = Vec::with_capacity(pair_count + 1);
+        let mut pixels = Vec::with_capacity(pixel_count);
+        let mut coverage = partial.then(|| Vec::with_capacity(pixel_count));
+        offsets.push(0);
+
+        for (row_lengths, row_pixels, row_coverage) in rows {
+            for length in row_lengths {
+                offsets.push(offsets.last().unwrap() + length);
+            }
+            pixels.extend(row_pixels);
+            if let Some(coverage) = &mut coverage {
+                coverage.extend(row_coverage);
+            }
+        }
+
+        Self {
+            nails: nails.to_vec(),
+            indices: nails
+                .iter()
+                .enumerate()
+                .map(|(i, nail)| (*nail, i))
+                .collect(),
+            width,
+            offsets,
+            pixels,
+            coverage,
+            skipped: vec![false; pair_count],
+        }
+    }
+
+    pub fn nails(&self) -> &[Nail] {
+        &self.nails
+    }
+
+    /// Position of `nail` in [`Chords::nails`].
+    pub fn index(&self, nail: Nail) -> Option<usize> {
+        self.indices.get(&nail).copied()
+    }
+
+    /// The chord between two nails in either direction, `None` if either isn't
+    /// a nail, they are the same nail or the chord was skipped.
+    pub fn get(&self, from: Nail, to: Nail) -> Option<Chord<'_>> {
+        self.chord_at(self.pair(from, to)?)
+    }
+
+    /// Number of pairs of nails, skipped chords included.
+    pub fn pair_count(&self) -> usize {
+        self.skipped.len()
+    }
+
+    /// Index of the chord between two nails, from 0 to [`Chords::pair_count`],
+    /// the same in either direction. `None` where [`Chords::get`] is.
+    pub fn pair(&self, from: Nail, to: Nail) -> Option<usize> {
+        let pair = self.pair_of(self.index(from)?, self.index(to)?)?;
+        (!self.skipped[pair]).then_some(pair)
+    }
+
+    /// The chord with index `pair`, `None` if it was skipped.
+    pub fn chord_at(&self, pair: usize) -> Option<Chord<'_>> {
+        (!self.skipped[pair]).then(|| self.chord(pair))
+    }
+
+    /// Whether some chord covers only part of a pixel.
+    pub fn partial_coverage(&self) -> bool {
+        self.coverage.is_some()
+    }
+
+    /// Every chord from `nail`, in nail order.
+    pub fn from(&self, nail: Nail) -> impl Iterator<Item = (Nail, Chord<'_>)> {
+        self.pairs_from(nail)
+            .map(|(other, pair)| (other, self.chord(pair)))
+    }
+
+    /// The other nail and the index of every chord from `nail`, in nail order.
+    pub fn pairs_from(&self, nail: Nail) -> impl Iterator<Item = (Nail, usize)> + '_ {
+        let from = self.index(nail);
+
+        self.nails
+            .iter()
+            .enumerate()
+            .filter_map(move |(to, other)| {
+                let pair = self.pair_of(from?, to)?;
+                (!self.skipped[pair]).then_some((*other, pair))
+            })
+    }
+
+    /// [`Chords::pairs_from`] as a parallel iterator.
+    pub fn par_pairs_from(&self, nail: Nail) -> impl ParallelIterator<Item = (Nail, usize)> + '_ {
+        let from = self.index(nail);
+
+        self.nails
+            .par_iter()
+            .enumerate()
+            .filter_map(move |(to, other)| {
+                let pair = self.pair_of(from?, to)?;
+                (!self.skipped[pair]).then_some((*other, pair))
+            })
+    }
+
+    /// Leave the chord between two nails out, see [`crate::board::BlockedChords::Skip`].
+    pub fn skip(&mut self, from: Nail, to: Nail) {
+        if let Some(pair) = self.pair_of(self.index(from).unwrap(), self.index(to).unwrap()) {
+            self.skipped[pair] = true;
+        }
+    }
+
+    /// The same chords with only the pixels `keep` accepts.
+    pub fn retain<F>(&self, keep: F) -> Self
+    where
+        F: Fn(u32, u32) -> bool,
+    {
+        let mut offsets = Vec::with_capacity(self.offsets.len());
+        let mut pixels = Vec::new();
+        let mut coverage = self.coverage.as_ref().map(|_| Vec::new());
+        offsets.push(0);
+
+        for pair in 0..self.skipped.len() {
+            let chord = self.chord(pair);
+            for ((x, y, c), pixel) in chord.coverage().zip(chord.pixels) {
+                if keep(x, y) {
+                    pixels.push(*pixel);
+                    if let Some(coverage) = &mut coverage {
+                        coverage.push(c);
+                    }
+                }
+            }
+            offsets.push(pixels.len());
+        }
+
+        Self {
+            nails: self.nails.clone(),
+            indices: self.indices.clone(),
+            width: self.width,
+            offsets,
+            pixels,
+            coverage,
+            skipped: self.skipped.clone(),
+        }
+    }
+
+    /// Index of the pair of nails `i` and `j` in either order, row by row
+    /// from the lower index.
+    fn pair_of(&self, i: usize, j: usize) -> Option<usize> {
+        let (i, j) = match i.cmp(&j) {
+            std::cmp::Ordering::Less => (i, j),
+            std::cmp::Ordering::Greater => (j, i),
+            std::cmp::Ordering::Equal => return None,
+        };
+        let n = self.nails.len();
+
+        Some(i * n - i * (i + 1) / 2 + j - i - 1)
+    }
+
+    fn chord(&self, pair: usize) -> Chord<'_> {
+        let range = self.offsets[pair]..self.offsets[pair + 1];
+
+        Chord {
+            width: self.width,
+            pixels: &self.pixels[range.clone()],
+            coverage: self.coverage.as_ref().map(|coverage| &coverage[range]),
+        }
+    }
+}
+
+impl fmt::Debug for Chords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chords")
+            .field("nails", &self.nails.len())
+            .field("chords", &self.skipped.iter().filter(|s| !**s).count())
+            .field("pixels", &self.pixels.len())
+            .finish()
+    }
+}
+
+impl<'a> Chord<'a> {
+    pub fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    /// Row major index of every pixel and how much of it the chord covers.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, f32)> + 'a {
+        let coverage = self.coverage;
+
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(move |(n, pixel)| (*pixel as usize, coverage.map_or(1.0, |c| c[n])))
+    }
+
+    /// Every pixel as coordinates and coverage.
+    pub fn coverage(&self) -> impl Iterator<Item = Coverage> + 'a {
+        let width = self.width;
+
+        self.iter()
+            .map(move |(i, c)| (i as u32 % width, i as u32 / width, c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chords_are_stored_once() {
+        let nails = vec![Nail(0, 0), Nail(0, 2), Nail(2, 0), Nail(2, 2)];
+        let mut chords = Chords::new(&nails, &Dimensions::new(3, 3), |i, j| {
+            vec![(i as u32, j as u32 - 1, 1.0)]
+        });
+
+        let pixels = |from, to| {
+            chords
+                .get(from, to)
+                .map(|chord| chord.coverage().collect::<Vec<_>>())
+        };
+        assert_eq!(pixels(Nail(0, 2), Nail(2, 2)), Some(vec![(1, 2, 1.0)]));
+        assert_eq!(pixels(Nail(2, 2), Nail(0, 2)), Some(vec![(1, 2, 1.0)]));
+        assert_eq!(pixels(Nail(0, 0), Nail(0, 0)), None);
+        assert_eq!(pixels(Nail(1, 1), Nail(0, 0)), None);
+        assert_eq!(chords.pixels.len(), 6);
+        assert!(chords.coverage.is_none());
+
+        chords.skip(Nail(2, 0), Nail(0, 0));
+        assert!(chords.get(Nail(0, 0), Nail(2, 0)).is_none());
+        assert_eq!(
+            chords
+                .from(Nail(0, 0))
+                .map(|(to, _)| to)
+                .collect::<Vec<_>>(),
+            vec![Nail(0, 2), Nail(2, 2)]
+        );
+    }
+
+    #[test]
+    fn test_retain() {
+        let nails = vec![Nail(0, 0), Nail(3, 0)];
+        let chords = Chords::new(&nails, &Dimensions::new(4, 1), |_, _| {
+            vec![(1, 0, 0.5), (2, 0, 1.0)]
+        });
+
+        let retained = chords.retain(|x, _| x != 1);
+        let chord = retained.get(Nail(0, 0), Nail(3, 0)).unwrap();
+        assert_eq!(chord.iter().collect::<Vec<_>>(), vec![(2, 1.0)]);
+        assert_eq!(chords.get(Nail(3, 0), Nail(0, 0)).unwrap().len(), 2);
+    }
+}
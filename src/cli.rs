@@ -0,0 +1,468 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use image::Rgb;
+
+use stringify::board::BlockedChords;
+use stringify::config::{
+    AlgorithmConfig, AlgorithmKind, AnimationConfig, BoardConfig, BoardShape, CheckpointConfig,
+    Config, OutputConfig, RefineConfig, RenderConfig, StopConfig, ThreadConfig,
+};
+use stringify::export::Paper;
+use stringify::image_utils::ColorDistance;
+use stringify::util::parse_hex_color;
+
+#[derive(Debug, Parser)]
+#[command(name = "stringify", about = "Turn images into string art patterns")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Seed for picking palettes, runs with the same seed give the same art.
+    /// 0 unless a config file says otherwise
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the string art algorithm and save the resulting art
+    Generate(Box<GenerateArgs>),
+    /// Run a job described by a TOML config file
+    Run(RunArgs),
+    /// Carry on with a run from its last checkpoint
+    Resume(ResumeArgs),
+    /// Extract a thread palette from an image using k-means
+    Palette(PaletteArgs),
+    /// Scale an image to the board and dither it to the palette
+    Dither(DitherArgs),
+    /// Show the scaled image with the nail positions drawn on top
+    Preview(PreviewArgs),
+    /// Save a printable 1:1 SVG template of the nail positions
+    Template(TemplateArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct BoardArgs {
+    #[arg(long, value_enum, default_value_t = Shape::Circle)]
+    pub shape: Shape,
+
+    /// Number of nails around the board
+    #[arg(long, default_value_t = 200)]
+    pub nail_count: u32,
+
+    /// Diameter of a circular board in millimetres
+    #[arg(long, default_value_t = 500.0)]
+    pub diameter_mm: f64,
+
+    /// Width of a rectangular or square board in millimetres
+    #[arg(long)]
+    pub width_mm: Option<f64>,
+
+    /// Height of a rectangular board in millimetres, defaults to the image aspect ratio
+    #[arg(long)]
+    pub height_mm: Option<f64>,
+
+    /// SVG file with the outline of an outline board, scaled to --width-mm if given
+    #[arg(long)]
+    pub outline: Option<PathBuf>,
+
+    /// CSV or JSON file with nail positions in millimetres for a nails board
+    #[arg(long)]
+    pub nails: Option<PathBuf>,
+
+    /// What a nails board does with chords that cross another nail, allow
+    /// keeps threads that would run through it
+    #[arg(long, value_enum, default_value_t = BlockedChordsArg::Skip)]
+    pub blocked_chords: BlockedChordsArg,
+
+    /// Score penalty in pixels for crossing chords with --blocked-chords penalize
+    #[arg(long, default_value_t = 10)]
+    pub chord_penalty: i32,
+
+    /// Diameter of the nails in millimetres
+    #[arg(long, default_value_t = 0.0)]
+    pub nail_diameter_mm: f64,
+
+    /// Width of the thread in millimetres, 0 draws single pixel lines
+    #[arg(long, default_value_t = 0.0)]
+    pub thread_width_mm: f64,
+
+    /// Resolution of the working image in pixels per millimetre of board
+    #[arg(long, default_value_t = 0.4)]
+    pub pixels_per_mm: f64,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Shape {
+    Circle,
+    Rectangle,
+    Square,
+    Outline,
+    Nails,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum BlockedChordsArg {
+    Allow,
+    Skip,
+    Penalize,
+}
+
+#[derive(Debug, Args)]
+pub struct PaletteChoice {
+    /// Thread colors as comma separated hex values, e.g. d6babd,6b607a,140917
+    #[arg(long, value_delimiter = ',', value_parser = parse_hex_color, conflicts_with = "colors")]
+    pub palette: Option<Vec<Rgb<u8>>>,
+
+    /// Number of thread colors to extract from the image when no palette is given
+    #[arg(long, default_value_t = 5)]
+    pub colors: usize,
+
+    /// How to compare colors when extracting the palette, dithering and blending
+    #[arg(long, value_enum, default_value_t = ColorDistanceArg::Rgb)]
+    pub color_distance: ColorDistanceArg,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ColorDistanceArg {
+    /// Squared distance between RGB values
+    Rgb,
+    /// ΔE76 in CIELAB
+    Cie76,
+    /// ΔE2000 in CIELAB, closest to what people see but the slowest
+    Ciede2000,
+    /// Distance in OKLab
+    Oklab,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Algorithm {
+    /// Greedy multi-color chord selection on the dithered image
+    Stringifier,
+    /// A single thread building up darkness, black unless --palette gives one color
+    Grayscale,
+    /// Translucent threads that mix optically where they overlap
+    Blend,
+    /// Stringifier that searches several moves ahead instead of taking the best chord
+    BeamSearch,
+}
+
+#[derive(Debug, Args)]
+pub struct GenerateArgs {
+    /// Source image
+    pub input: PathBuf,
+
+    /// Where to save the finished art
+    #[arg(short, long, default_value = "art.png")]
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub board: BoardArgs,
+
+    #[command(flatten)]
+    pub palette: PaletteChoice,
+
+    #[arg(long, value_enum, default_value_t = Algorithm::Stringifier)]
+    pub algo: Algorithm,
+
+    /// How much a single line hides below it in grayscale and blend mode
+    #[arg(long)]
+    pub line_opacity: Option<f32>,
+
+    /// Grayscale and blend stop once no line improves the image by more than this
+    #[arg(long)]
+    pub min_improvement: Option<f32>,
+
+    /// Board color as hex, which blend threads are laid on and renders show
+    #[arg(long, value_parser = parse_hex_color)]
+    pub background: Option<Rgb<u8>>,
+
+    /// Sequences of moves beam search keeps at every depth, defaults to 4
+    #[arg(long)]
+    pub beam_width: Option<usize>,
+
+    /// Moves beam search looks ahead, defaults to 3
+    #[arg(long)]
+    pub beam_depth: Option<usize>,
+
+    /// Save build instructions here, as .csv, .json or .txt
+    #[arg(long)]
+    pub pattern: Option<PathBuf>,
+
+    /// Also save the art as an SVG vector drawing
+    #[arg(long)]
+    pub svg: Option<PathBuf>,
+
+    /// Also save a high resolution preview of the finished board
+    #[arg(long)]
+    pub render: Option<PathBuf>,
+
+    /// Resolution of the preview
+    #[arg(long, default_value_t = 4.0, requires = "render")]
+    pub render_pixels_per_mm: f64,
+
+    /// Image to show on the board in the preview, e.g. a wood texture
+    #[arg(long, requires = "render")]
+    pub render_texture: Option<PathBuf>,
+
+    /// Record the art being strung, as a .gif or as numbered .png frames
+    #[arg(long)]
+    pub animation: Option<PathBuf>,
+
+    /// Record an animation frame every N steps
+    #[arg(long, default_value_t = 50, requires = "animation")]
+    pub animation_every: usize,
+
+    /// Save the art in progress every N steps
+    #[arg(long)]
+    pub save_every: Option<usize>,
+
+    /// Save the run to this JSON file every so often, see `resume`
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Save a checkpoint every N steps
+    #[arg(long, default_value_t = 500, requires = "checkpoint")]
+    pub checkpoint_every: usize,
+
+    /// Stop after this many steps
+    #[arg(long)]
+    pub max_steps: Option<usize>,
+
+    /// Once done, try this many changes to the pattern and keep those that
+    /// bring the art closer to the image
+    #[arg(long)]
+    pub refine: Option<usize>,
+
+    /// Starting temperature for simulated annealing while refining, 0 only
+    /// keeps improvements
+    #[arg(long, default_value_t = 0.0, requires = "refine")]
+    pub refine_temperature: f64,
+
+    /// Write the job as a TOML config so it can be reproduced with `run`
+    #[arg(long)]
+    pub save_config: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// TOML job description
+    pub config: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ResumeArgs {
+    /// Checkpoint saved by `generate --checkpoint` or a config with output.checkpoint
+    pub checkpoint: PathBuf,
+
+    /// Stop after this many steps in total, instead of the limit the run had
+    #[arg(long)]
+    pub max_steps: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct PaletteArgs {
+    /// Source image
+    pub input: PathBuf,
+
+    /// Number of colors to extract
+    #[arg(long, default_value_t = 5)]
+    pub colors: usize,
+
+    /// How to compare colors while extracting them
+    #[arg(long, value_enum, default_value_t = ColorDistanceArg::Rgb)]
+    pub color_distance: ColorDistanceArg,
+}
+
+#[derive(Debug, Args)]
+pub struct DitherArgs {
+    /// Source image
+    pub input: PathBuf,
+
+    /// Where to save the dithered image
+    #[arg(short, long, default_value = "dithered.png")]
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub board: BoardArgs,
+
+    #[command(flatten)]
+    pub palette: PaletteChoice,
+
+    /// Also save one black and white mask per color into this directory
+    #[arg(long)]
+    pub masks: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct PreviewArgs {
+    /// Source image
+    pub input: PathBuf,
+
+    /// Where to save the preview
+    #[arg(short, long, default_value = "preview.png")]
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub board: BoardArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct TemplateArgs {
+    /// Source image, sets the height of rectangular boards without one
+    pub input: PathBuf,
+
+    /// Where to save the template. Templates spanning several pages are
+    /// saved as numbered files next to it
+    #[arg(short, long, default_value = "template.svg")]
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub board: BoardArgs,
+
+    /// Paper to tile the template across
+    #[arg(long, value_enum, default_value_t = PaperArg::A4)]
+    pub paper: PaperArg,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PaperArg {
+    A4,
+    Letter,
+    /// A single sheet as large as the board
+    None,
+}
+
+impl PaperArg {
+    pub fn to_paper(self) -> Option<Paper> {
+        match self {
+            PaperArg::A4 => Some(Paper::A4),
+            PaperArg::Letter => Some(Paper::Letter),
+            PaperArg::None => None,
+        }
+    }
+}
+
+impl GenerateArgs {
+    pub fn to_config(&self, seed: u64) -> Config {
+        let kind = match self.algo {
+            Algorithm::Stringifier => AlgorithmKind::Stringifier,
+            Algorithm::Grayscale => AlgorithmKind::Grayscale,
+            Algorithm::Blend => AlgorithmKind::Blend,
+            Algorithm::BeamSearch => AlgorithmKind::BeamSearch,
+        };
+        let threads = match self.algo {
+            Algorithm::Grayscale if self.palette.palette.is_none() => ThreadConfig {
+                colors: Some(vec![Rgb([0, 0, 0])]),
+                extract: None,
+                color_distance: self.palette.color_distance.to_config(),
+            },
+            _ => self.palette.to_config(),
+        };
+
+        Config {
+            input: self.input.clone(),
+            seed,
+            board: self.board.to_config(),
+            threads,
+            algorithm: AlgorithmConfig {
+                kind,
+                line_opacity: self.line_opacity,
+                min_improvement: self.min_improvement,
+                background: self.background,
+                beam_width: self.beam_width,
+                beam_depth: self.beam_depth,
+            },
+            stop: StopConfig {
+                max_steps: self.max_steps,
+            },
+            refine: self.refine.map(|iterations| RefineConfig {
+                iterations,
+                temperature: self.refine_temperature,
+            }),
+            output: OutputConfig {
+                art: self.output.clone(),
+                save_every: self.save_every,
+                dithered: None,
+                pattern: self.pattern.clone(),
+                svg: self.svg.clone(),
+                render: self.render.as_ref().map(|path| RenderConfig {
+                    path: path.clone(),
+                    pixels_per_mm: Some(self.render_pixels_per_mm),
+                    texture: self.render_texture.clone(),
+                    opacity: None,
+                }),
+                animation: self.animation.as_ref().map(|path| AnimationConfig {
+                    path: path.clone(),
+                    every: self.animation_every,
+                    frame_delay_ms: None,
+                }),
+                checkpoint: self.checkpoint.as_ref().map(|path| CheckpointConfig {
+                    path: path.clone(),
+                    every: self.checkpoint_every,
+                }),
+            },
+        }
+    }
+}
+
+impl BoardArgs {
+    pub fn to_config(&self) -> BoardConfig {
+        let (shape, diameter_mm) = match self.shape {
+            Shape::Circle => (BoardShape::Circle, Some(self.diameter_mm)),
+            Shape::Rectangle => (BoardShape::Rectangle, None),
+            Shape::Square => (BoardShape::Square, None),
+            Shape::Outline => (BoardShape::Outline, None),
+            Shape::Nails => (BoardShape::Nails, None),
+        };
+        let blocked_chords = match self.blocked_chords {
+            BlockedChordsArg::Allow => BlockedChords::Allow,
+            BlockedChordsArg::Skip => BlockedChords::Skip,
+            BlockedChordsArg::Penalize => BlockedChords::Penalize(self.chord_penalty),
+        };
+
+        BoardConfig {
+            shape,
+            nail_count: self.nail_count,
+            diameter_mm,
+            width_mm: self.width_mm,
+            height_mm: self.height_mm,
+            outline: self.outline.clone(),
+            nails: self.nails.clone(),
+            points: None,
+            nail_diameter_mm: self.nail_diameter_mm,
+            thread_width_mm: self.thread_width_mm,
+            blocked_chords,
+            pixels_per_mm: self.pixels_per_mm,
+        }
+    }
+}
+
+impl PaletteChoice {
+    fn to_config(&self) -> ThreadConfig {
+        match &self.palette {
+            Some(colors) => ThreadConfig {
+                colors: Some(colors.clone()),
+                extract: None,
+                color_distance: self.color_distance.to_config(),
+            },
+            None => ThreadConfig {
+                colors: None,
+                extract: Some(self.colors),
+                color_distance: self.color_distance.to_config(),
+            },
+        }
+    }
+}
+
+impl ColorDistanceArg {
+    pub fn to_config(self) -> ColorDistance {
+        match self {
+            ColorDistanceArg::Rgb => ColorDistance::Rgb,
+            ColorDistanceArg::Cie76 => ColorDistance::Cie76,
+            ColorDistanceArg::Ciede2000 => ColorDistance::Ciede2000,
+            ColorDistanceArg::Oklab => ColorDistance::Oklab,
+        }
+    }
+}
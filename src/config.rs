@@ -0,0 +1,968 @@
+use std::path::{Component, Path, PathBuf};
+
+use image::{DynamicImage, Rgb};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    board::{BlockedChords, Board, NailPosition},
+    image_utils::{kmeans, ColorDistance},
+    nail_list, outline,
+    stringifier::MAX_COLORS,
+    util::{parse_hex_color, to_hex_color},
+};
+
+/// A complete string art job. Everything needed to reproduce a finished piece
+/// lives in one TOML file, e.g.
+///
+/// ```toml
+/// input = "pikachu.jpg"
+///
+/// [board]
+/// shape = "circle"
+/// nail_count = 200
+/// diameter_mm = 500.0
+/// pixels_per_mm = 0.4
+///
+/// [threads]
+/// colors = ["d6babd", "6b607a", "140917"]
+///
+/// [algorithm]
+/// kind = "stringifier"
+///
+/// [stop]
+/// max_steps = 4000
+///
+/// [output]
+/// art = "art.png"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub input: PathBuf,
+    /// Seed for everything random, the same seed always gives the same art
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub seed: u64,
+    pub board: BoardConfig,
+    pub threads: ThreadConfig,
+    #[serde(default)]
+    pub algorithm: AlgorithmConfig,
+    #[serde(default)]
+    pub stop: StopConfig,
+    /// Improve the pattern once the algorithm is done, see [`crate::refine::refine`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refine: Option<RefineConfig>,
+    #[serde(default)]
+    pub output: OutputConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardShape {
+    #[default]
+    Circle,
+    Rectangle,
+    Square,
+    /// Any closed outline, from an SVG file or a list of points
+    Outline,
+    /// Nails at explicit positions, from a CSV or JSON file or a list of points
+    Nails,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoardConfig {
+    #[serde(default)]
+    pub shape: BoardShape,
+    pub nail_count: u32,
+    /// Size of circular boards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diameter_mm: Option<f64>,
+    /// Size of rectangular and square boards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width_mm: Option<f64>,
+    /// Height of rectangular boards, follows the source image aspect ratio when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height_mm: Option<f64>,
+    /// SVG file holding the outline of an outline board
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outline: Option<PathBuf>,
+    /// CSV or JSON file holding the nail positions of a nails board
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nails: Option<PathBuf>,
+    /// Outline of an outline board or nail positions of a nails board, in millimetres
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub points: Option<Vec<(f64, f64)>>,
+    /// Thread runs between the sides of the nails rather than their centres
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub nail_diameter_mm: f64,
+    /// Thread width for anti-aliased chords, single pixel lines when left out
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub thread_width_mm: f64,
+    /// What nails boards do with chords that cross another nail, skipped when
+    /// left out
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub blocked_chords: BlockedChords,
+    /// Resolution of the working image
+    pub pixels_per_mm: f64,
+}
+
+impl BoardConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.nail_count < 2 {
+            return Err("board.nail_count must be at least 2".to_string());
+        }
+        if self.pixels_per_mm <= 0.0 {
+            return Err("board.pixels_per_mm must be greater than 0".to_string());
+        }
+
+        let positive = |name: &str, value: Option<f64>| match value {
+            Some(value) if value <= 0.0 => Err(format!("board.{} must be greater than 0", name)),
+            _ => Ok(()),
+        };
+        positive("diameter_mm", self.diameter_mm)?;
+        positive("width_mm", self.width_mm)?;
+        positive("height_mm", self.height_mm)?;
+        if self.nail_diameter_mm < 0.0 || !self.nail_diameter_mm.is_finite() {
+            return Err("board.nail_diameter_mm must be a number not below 0".to_string());
+        }
+        if self.thread_width_mm < 0.0 || !self.thread_width_mm.is_finite() {
+            return Err("board.thread_width_mm must be a number not below 0".to_string());
+        }
+
+        match self.shape {
+            BoardShape::Circle if self.diameter_mm.is_none() => {
+                Err("circle boards need board.diameter_mm".to_string())
+            }
+            BoardShape::Rectangle | BoardShape::Square if self.width_mm.is_none() => {
+                Err("rectangle and square boards need board.width_mm".to_string())
+            }
+            BoardShape::Rectangle | BoardShape::Square if !self.nail_count.is_multiple_of(2) => {
+                Err("rectangle and square boards need an even board.nail_count".to_string())
+            }
+            BoardShape::Rectangle | BoardShape::Square if self.nail_count < 4 => {
+                Err("rectangle and square boards need at least 4 nails".to_string())
+            }
+            BoardShape::Outline if self.outline.is_some() == self.points.is_some() => {
+                Err("outline boards need either board.outline or board.points".to_string())
+            }
+            BoardShape::Outline if self.points.as_ref().is_some_and(|p| p.len() < 3) => {
+                Err("board.points needs at least 3 points".to_string())
+            }
+            BoardShape::Nails if self.nails.is_some() == self.points.is_some() => {
+                Err("nails boards need either board.nails or board.points".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Build the board. Rectangular boards without a height take their aspect
+    /// ratio from `src_img`. Outline boards are scaled to `width_mm` if given,
+    /// otherwise their coordinates are taken as millimetres. Nails boards are as
+    /// large as their furthest nail unless `width_mm` and `height_mm` say otherwise.
+    pub fn build(&self, src_img: &DynamicImage) -> Result<Board, String> {
+        self.validate()?;
+
+        let board = match self.shape {
+            BoardShape::Circle => Board::new(
+                self.diameter_mm.unwrap(),
+                self.nail_count,
+                self.pixels_per_mm,
+            )?,
+            BoardShape::Square => Board::new_rectangle(
+                self.width_mm.unwrap(),
+                self.width_mm.unwrap(),
+                self.nail_count,
+                self.pixels_per_mm,
+            )?,
+            BoardShape::Rectangle => match self.height_mm {
+                Some(height_mm) => Board::new_rectangle(
+                    self.width_mm.unwrap(),
+                    height_mm,
+                    self.nail_count,
+                    self.pixels_per_mm,
+                )?,
+                None => Board::rectangle_for_image(
+                    src_img,
+                    self.width_mm.unwrap(),
+                    self.nail_count,
+                    self.pixels_per_mm,
+                )?,
+            },
+            BoardShape::Outline => {
+                let outline = match (&self.outline, &self.points) {
+                    (Some(path), _) => outline::load_svg(path)
+                        .map_err(|err| format!("{}: {}", path.display(), err))?,
+                    (None, Some(points)) => points.clone(),
+                    (None, None) => unreachable!("validated above"),
+                };
+                let outline = match self.width_mm {
+                    Some(width_mm) => outline::fit_to_width(&outline, width_mm)?,
+                    None => outline,
+                };
+                Board::from_outline(&outline, self.nail_count, self.pixels_per_mm)?
+            }
+            BoardShape::Nails => {
+                let positions = match (&self.nails, &self.points) {
+                    (Some(path), _) => nail_list::load(path)
+                        .map_err(|err| format!("{}: {}", path.display(), err))?,
+                    (None, Some(points)) => {
+                        points.iter().map(|(x, y)| NailPosition(*x, *y)).collect()
+                    }
+                    (None, None) => unreachable!("validated above"),
+                };
+                if positions.len() < 2 {
+                    return Err("nails boards need at least 2 nails".to_string());
+                }
+                let width_mm = self
+                    .width_mm
+                    .unwrap_or_else(|| positions.iter().fold(0.0, |max, p| p.0.max(max)));
+                let height_mm = self
+                    .height_mm
+                    .unwrap_or_else(|| positions.iter().fold(0.0, |max, p| p.1.max(max)));
+                Board::from_nails(
+                    width_mm,
+                    height_mm,
+                    positions,
+                    self.pixels_per_mm,
+                    self.blocked_chords,
+                )?
+            }
+        };
+
+        board
+            .with_nail_diameter(self.nail_diameter_mm)?
+            .with_thread_width(self.thread_width_mm)
+    }
+}
+
+/// Either an explicit list of thread colors or the number of colors to
+/// extract from the source image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThreadConfig {
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_colors",
+        deserialize_with = "deserialize_colors"
+    )]
+    pub colors: Option<Vec<Rgb<u8>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract: Option<usize>,
+    /// How colors are compared when extracting the palette, dithering and
+    /// blending, RGB when left out
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub color_distance: ColorDistance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AlgorithmKind {
+    #[default]
+    Stringifier,
+    /// A single dark thread building up darkness, needs exactly one thread color
+    Grayscale,
+    /// Translucent threads of all colors mixing optically where they overlap
+    Blend,
+    /// The stringifier looking several moves ahead, see [`crate::BeamSearch`]
+    BeamSearch,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AlgorithmConfig {
+    #[serde(default)]
+    pub kind: AlgorithmKind,
+    /// How much a single grayscale or blend line hides below it, 0.2 when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_opacity: Option<f32>,
+    /// Grayscale and blend stop once no line improves the art by more than this,
+    /// for blend in squared RGB with channels from 0 to 1 whatever the
+    /// `color_distance`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_improvement: Option<f32>,
+    /// Color of the board, which blend threads are laid on and renders show.
+    /// White when left out
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_color",
+        deserialize_with = "deserialize_color"
+    )]
+    pub background: Option<Rgb<u8>>,
+    /// Sequences of moves a beam search keeps at every depth, 4 when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beam_width: Option<usize>,
+    /// Moves a beam search looks ahead, 3 when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beam_depth: Option<usize>,
+}
+
+impl AlgorithmConfig {
+    pub fn line_opacity(&self) -> f32 {
+        self.line_opacity.unwrap_or(0.2)
+    }
+
+    pub fn min_improvement(&self) -> f32 {
+        self.min_improvement.unwrap_or(0.0)
+    }
+
+    pub fn background(&self) -> Rgb<u8> {
+        self.background.unwrap_or(Rgb([255, 255, 255]))
+    }
+
+    pub fn beam_width(&self) -> usize {
+        self.beam_width.unwrap_or(4)
+    }
+
+    pub fn beam_depth(&self) -> usize {
+        self.beam_depth.unwrap_or(3)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct StopConfig {
+    /// Stop after this many steps even if the algorithm could go on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_steps: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RefineConfig {
+    /// Changes to try
+    pub iterations: usize,
+    /// Starting temperature of simulated annealing, 0 only keeps changes that
+    /// make the art better
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub temperature: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    #[serde(default = "default_art_path")]
+    pub art: PathBuf,
+    /// Save the art in progress every N steps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save_every: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dithered: Option<PathBuf>,
+    /// Build instructions, as CSV, JSON or plain text depending on the extension
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<PathBuf>,
+    /// Vector drawing of the art, one line per thread segment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub svg: Option<PathBuf>,
+    /// High resolution preview of the finished board
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render: Option<RenderConfig>,
+    /// The art being strung, as an animated GIF or numbered PNG frames
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<AnimationConfig>,
+    /// Save the run every so often so it can be resumed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<CheckpointConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckpointConfig {
+    /// JSON file, overwritten with every checkpoint
+    pub path: PathBuf,
+    /// Save a checkpoint every N steps
+    pub every: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationConfig {
+    /// `.gif` for an animation, `.png` for numbered frames next to it
+    pub path: PathBuf,
+    /// Record a frame every N steps
+    pub every: usize,
+    /// How long every frame of a GIF shows, 100 when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_delay_ms: Option<u32>,
+}
+
+impl AnimationConfig {
+    pub fn frame_delay_ms(&self) -> u32 {
+        self.frame_delay_ms.unwrap_or(100)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderConfig {
+    pub path: PathBuf,
+    /// Resolution of the render, 4 when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pixels_per_mm: Option<f64>,
+    /// Image to show on the board instead of a plain background color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<PathBuf>,
+    /// Thread opacity, the algorithm's line opacity when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f32>,
+}
+
+impl RenderConfig {
+    pub fn pixels_per_mm(&self) -> f64 {
+        self.pixels_per_mm.unwrap_or(4.0)
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            art: default_art_path(),
+            save_every: None,
+            dithered: None,
+            pattern: None,
+            svg: None,
+            render: None,
+            animation: None,
+            checkpoint: None,
+        }
+    }
+}
+
+fn default_art_path() -> PathBuf {
+    PathBuf::from("art.png")
+}
+
+impl Config {
+    /// Load a job from a TOML file. Relative paths inside the file are
+    /// resolved against the directory the file lives in.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let mut config = Config::from_toml(&text)?;
+
+        if let Some(dir) = path.parent() {
+            config.resolve_paths(dir);
+        }
+
+        Ok(config)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.board.validate()?;
+
+        if !(self.algorithm.line_opacity() > 0.0 && self.algorithm.line_opacity() <= 1.0) {
+            return Err("algorithm.line_opacity must be between 0 and 1".to_string());
+        }
+        if self.algorithm.beam_width() == 0 || self.algorithm.beam_depth() == 0 {
+            return Err("algorithm.beam_width and beam_depth must be greater than 0".to_string());
+        }
+        if self
+            .refine
+            .as_ref()
+            .is_some_and(|refine| refine.temperature < 0.0 || refine.temperature.is_nan())
+        {
+            return Err("refine.temperature must not be negative".to_string());
+        }
+        if matches!(
+            self.algorithm.kind,
+            AlgorithmKind::Stringifier | AlgorithmKind::BeamSearch
+        ) {
+            let count = match (&self.threads.colors, self.threads.extract) {
+                (Some(colors), _) => colors.len(),
+                (None, extract) => extract.unwrap_or(0),
+            };
+            if count > MAX_COLORS {
+                return Err(format!(
+                    "the stringifier takes at most {} thread colors",
+                    MAX_COLORS
+                ));
+            }
+        }
+        if self.algorithm.kind == AlgorithmKind::Grayscale {
+            let single = match (&self.threads.colors, self.threads.extract) {
+                (Some(colors), _) => colors.len() == 1,
+                (None, extract) => extract == Some(1),
+            };
+            if !single {
+                return Err("grayscale needs exactly one thread color".to_string());
+            }
+        }
+        if self
+            .output
+            .animation
+            .as_ref()
+            .is_some_and(|animation| animation.every == 0)
+        {
+            return Err("output.animation.every must be greater than 0".to_string());
+        }
+        if self
+            .output
+            .checkpoint
+            .as_ref()
+            .is_some_and(|checkpoint| checkpoint.every == 0)
+        {
+            return Err("output.checkpoint.every must be greater than 0".to_string());
+        }
+        if let Some(render) = &self.output.render {
+            if render.pixels_per_mm() <= 0.0 {
+                return Err("output.render.pixels_per_mm must be greater than 0".to_string());
+            }
+            if render
+                .opacity
+                .is_some_and(|opacity| !(opacity > 0.0 && opacity <= 1.0))
+            {
+                return Err("output.render.opacity must be between 0 and 1".to_string());
+            }
+        }
+
+        match (&self.threads.colors, self.threads.extract) {
+            (Some(_), Some(_)) => {
+                Err("threads.colors and threads.extract are mutually exclusive".to_string())
+            }
+            (None, None) => Err("threads needs either colors or extract".to_string()),
+            (Some(colors), None) if colors.is_empty() => {
+                Err("threads.colors must not be empty".to_string())
+            }
+            (None, Some(0)) => Err("threads.extract must be greater than 0".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Make relative paths relative to `dir`.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        self.map_paths(|path| {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        });
+    }
+
+    /// Rewrite paths given relative to `from` as relative to `dir`, so a
+    /// config saved into `dir` still points at the same files once loaded.
+    /// Both directories have to be absolute.
+    pub fn relative_paths(&mut self, from: &Path, dir: &Path) {
+        self.map_paths(|path| *path = relative_path(&from.join(&*path), dir));
+    }
+
+    fn map_paths<F: FnMut(&mut PathBuf)>(&mut self, mut map: F) {
+        map(&mut self.input);
+        if let Some(outline) = &mut self.board.outline {
+            map(outline);
+        }
+        if let Some(nails) = &mut self.board.nails {
+            map(nails);
+        }
+        map(&mut self.output.art);
+        if let Some(dithered) = &mut self.output.dithered {
+            map(dithered);
+        }
+        if let Some(pattern) = &mut self.output.pattern {
+            map(pattern);
+        }
+        if let Some(svg) = &mut self.output.svg {
+            map(svg);
+        }
+        if let Some(animation) = &mut self.output.animation {
+            map(&mut animation.path);
+        }
+        if let Some(checkpoint) = &mut self.output.checkpoint {
+            map(&mut checkpoint.path);
+        }
+        if let Some(render) = &mut self.output.render {
+            map(&mut render.path);
+            if let Some(texture) = &mut render.texture {
+                map(texture);
+            }
+        }
+    }
+
+    pub fn build_board(&self, src_img: &DynamicImage) -> Result<Board, String> {
+        self.board.build(src_img)
+    }
+
+    /// The thread colors, extracted from `src_img` if the job asks for that.
+    pub fn palette(&self, src_img: &DynamicImage) -> Result<Vec<Rgb<u8>>, String> {
+        match (&self.threads.colors, self.threads.extract) {
+            (Some(colors), _) => Ok(colors.clone()),
+            (None, Some(k)) => Ok(kmeans(
+                k,
+                &src_img.to_rgb8(),
+                self.threads.color_distance,
+                &mut StdRng::seed_from_u64(self.seed),
+            )),
+            (None, None) => Err("threads needs either colors or extract".to_string()),
+        }
+    }
+}
+
+/// Absolute `path` relative to absolute `dir`, worked out from the names alone.
+/// Stays absolute if they have nothing in common.
+fn relative_path(path: &Path, dir: &Path) -> PathBuf {
+    let (path, dir) = (normalize(path), normalize(dir));
+    let common = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return path;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in dir.components().skip(common) {
+        relative.push("..");
+    }
+    for component in path.components().skip(common) {
+        relative.push(component);
+    }
+    relative
+}
+
+/// `path` without `.` and with `..` taken out along with the name before it.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn serialize_colors<S: Serializer>(
+    colors: &Option<Vec<Rgb<u8>>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let hex = colors
+        .as_ref()
+        .map(|colors| colors.iter().map(to_hex_color).collect::<Vec<_>>());
+    hex.serialize(serializer)
+}
+
+fn serialize_color<S: Serializer>(
+    color: &Option<Rgb<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    color.as_ref().map(to_hex_color).serialize(serializer)
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Rgb<u8>>, D::Error> {
+    let hex: Option<String> = Option::deserialize(deserializer)?;
+
+    hex.map(|hex| parse_hex_color(&hex).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+fn deserialize_colors<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<Rgb<u8>>>, D::Error> {
+    let hex: Option<Vec<String>> = Option::deserialize(deserializer)?;
+
+    hex.map(|hex| {
+        hex.iter()
+            .map(|s| parse_hex_color(s).map_err(serde::de::Error::custom))
+            .collect()
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOB: &str = r##"
+        input = "pikachu.jpg"
+
+        [board]
+        nail_count = 200
+        diameter_mm = 500.0
+        pixels_per_mm = 0.4
+
+        [threads]
+        colors = ["d6babd", "#6b607a", "140917"]
+
+        [stop]
+        max_steps = 4000
+    "##;
+
+    #[test]
+    fn test_load_job() {
+        let config = Config::from_toml(JOB).expect("failed to parse job");
+
+        assert_eq!(config.board.shape, BoardShape::Circle);
+        assert_eq!(config.board.nail_count, 200);
+        assert_eq!(
+            config.threads.colors,
+            Some(vec![
+                Rgb([214, 186, 189]),
+                Rgb([107, 96, 122]),
+                Rgb([20, 9, 23])
+            ])
+        );
+        assert_eq!(config.algorithm.kind, AlgorithmKind::Stringifier);
+        assert_eq!(config.stop.max_steps, Some(4000));
+        assert_eq!(config.output.art, PathBuf::from("art.png"));
+    }
+
+    #[test]
+    fn test_nail_and_thread_sizes() {
+        for size in ["-1.0", "nan", "inf"] {
+            for name in ["nail_diameter_mm", "thread_width_mm"] {
+                let job = JOB.replace(
+                    "pixels_per_mm = 0.4",
+                    &format!("pixels_per_mm = 0.4\n{} = {}", name, size),
+                );
+                assert!(Config::from_toml(&job).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_rectangle_needs_width() {
+        let job = JOB.replace("diameter_mm = 500.0", "shape = \"rectangle\"");
+        assert!(Config::from_toml(&job).is_err());
+
+        let job = JOB.replace(
+            "diameter_mm = 500.0",
+            "shape = \"rectangle\"\nwidth_mm = 600.0",
+        );
+        let config = Config::from_toml(&job).unwrap();
+        assert_eq!(config.board.shape, BoardShape::Rectangle);
+        assert_eq!(config.board.width_mm, Some(600.0));
+        assert_eq!(config.board.height_mm, None);
+    }
+
+    #[test]
+    fn test_outline_points() {
+        let job = JOB.replace(
+            "diameter_mm = 500.0",
+            "shape = \"outline\"\npoints = [[0, 0], [1000, 0], [500, 800]]",
+        );
+        let config = Config::from_toml(&job).unwrap();
+        let board = config
+            .build_board(&DynamicImage::new_rgb8(1, 1))
+            .expect("failed to build board");
+
+        assert_eq!(board.nails().len(), 200);
+        assert!(board.mask().is_some());
+    }
+
+    #[test]
+    fn test_nails_board() {
+        let job = JOB.replace(
+            "diameter_mm = 500.0",
+            "shape = \"nails\"\npoints = [[0, 0], [100, 0], [50, 50], [0, 100]]\nblocked_chords = { penalize = 20 }",
+        );
+        let config = Config::from_toml(&job).unwrap();
+        assert_eq!(config.board.blocked_chords, BlockedChords::Penalize(20));
+
+        let board = config
+            .build_board(&DynamicImage::new_rgb8(1, 1))
+            .expect("failed to build board");
+        assert_eq!(board.nails().len(), 4);
+        assert_eq!(board.width_mm(), 100.0);
+
+        // threads through nails are an opt-out
+        let skipped = Config::from_toml(&job.replace("blocked_chords = { penalize = 20 }", ""));
+        assert_eq!(skipped.unwrap().board.blocked_chords, BlockedChords::Skip);
+        let allowed = Config::from_toml(&job.replace("{ penalize = 20 }", "\"allow\""));
+        assert_eq!(allowed.unwrap().board.blocked_chords, BlockedChords::Allow);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config = Config::from_toml(JOB).unwrap();
+        let text = config.to_toml().unwrap();
+
+        assert_eq!(Config::from_toml(&text).unwrap(), config);
+    }
+
+    #[test]
+    fn test_seeded_palette() {
+        let job = JOB
+            .replace(
+                "input = \"pikachu.jpg\"",
+                "input = \"pikachu.jpg\"\nseed = 42",
+            )
+            .replace(
+                r##"colors = ["d6babd", "#6b607a", "140917"]"##,
+                "extract = 3",
+            );
+        let config = Config::from_toml(&job).unwrap();
+        assert_eq!(config.seed, 42);
+        assert!(config.to_toml().unwrap().contains("seed = 42"));
+
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 16, |x, y| {
+            Rgb([(x * 16) as u8, (y * 16) as u8, 0])
+        }));
+        assert_eq!(config.palette(&img), config.palette(&img));
+
+        let mut unvalidated = config.clone();
+        unvalidated.threads.extract = None;
+        assert!(unvalidated.palette(&img).is_err());
+    }
+
+    #[test]
+    fn test_rejects_ambiguous_palette() {
+        let job = JOB.replace("[stop]", "extract = 5\n[stop]");
+
+        assert!(Config::from_toml(&job).is_err());
+    }
+
+    #[test]
+    fn test_grayscale_needs_one_color() {
+        let job = JOB.replace("[stop]", "[algorithm]\nkind = \"grayscale\"\n[stop]");
+        assert!(Config::from_toml(&job).is_err());
+
+        let job = job.replace(r##"["d6babd", "#6b607a", "140917"]"##, r#"["000000"]"#);
+        let config = Config::from_toml(&job).expect("failed to parse job");
+        assert_eq!(config.algorithm.kind, AlgorithmKind::Grayscale);
+        assert_eq!(config.algorithm.line_opacity(), 0.2);
+    }
+
+    #[test]
+    fn test_blend_background() {
+        let job = JOB.replace(
+            "[stop]",
+            "[algorithm]\nkind = \"blend\"\nbackground = \"#202020\"\n[stop]",
+        );
+        let config = Config::from_toml(&job).expect("failed to parse job");
+
+        assert_eq!(config.algorithm.kind, AlgorithmKind::Blend);
+        assert_eq!(config.algorithm.background(), Rgb([32, 32, 32]));
+        assert_eq!(
+            Config::from_toml(&config.to_toml().unwrap()).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_beam_search() {
+        let job = JOB.replace(
+            "[stop]",
+            "[algorithm]\nkind = \"beam_search\"\nbeam_width = 8\n[stop]",
+        );
+        let config = Config::from_toml(&job).expect("failed to parse job");
+
+        assert_eq!(config.algorithm.kind, AlgorithmKind::BeamSearch);
+        assert_eq!(config.algorithm.beam_width(), 8);
+        assert_eq!(config.algorithm.beam_depth(), 3);
+
+        let job = job.replace("beam_width = 8", "beam_depth = 0");
+        assert!(Config::from_toml(&job).is_err());
+    }
+
+    #[test]
+    fn test_refine() {
+        let job = JOB.replace("[stop]", "[refine]\niterations = 5000\n[stop]");
+        let config = Config::from_toml(&job).expect("failed to parse job");
+
+        let refine = config.refine.as_ref().unwrap();
+        assert_eq!((refine.iterations, refine.temperature), (5000, 0.0));
+        assert_eq!(
+            Config::from_toml(&config.to_toml().unwrap()).unwrap(),
+            config
+        );
+
+        let job = job.replace("iterations = 5000", "iterations = 5000\ntemperature = -1.0");
+        assert!(Config::from_toml(&job).is_err());
+    }
+
+    #[test]
+    fn test_color_distance() {
+        let config = Config::from_toml(JOB).unwrap();
+        assert_eq!(config.threads.color_distance, ColorDistance::Rgb);
+        assert!(!config.to_toml().unwrap().contains("color_distance"));
+
+        let job = JOB.replace(
+            r##"colors = ["d6babd", "#6b607a", "140917"]"##,
+            "extract = 2\ncolor_distance = \"ciede2000\"",
+        );
+        let config = Config::from_toml(&job).expect("failed to parse job");
+        assert_eq!(config.threads.color_distance, ColorDistance::Ciede2000);
+        assert_eq!(
+            Config::from_toml(&config.to_toml().unwrap()).unwrap(),
+            config
+        );
+
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 16, |x, _| {
+            Rgb([(x * 16) as u8, 0, 0])
+        }));
+        assert_eq!(config.palette(&img).unwrap().len(), 2);
+
+        assert!(Config::from_toml(&job.replace("ciede2000", "hsv")).is_err());
+    }
+
+    #[test]
+    fn test_relative_paths() {
+        let mut config = Config::from_toml(JOB).unwrap();
+        config.output.art = PathBuf::from("out/art.png");
+        config.output.svg = Some(PathBuf::from("/tmp/art.svg"));
+
+        config.relative_paths(Path::new("/work"), Path::new("/work/./jobs"));
+        assert_eq!(config.input, PathBuf::from("../pikachu.jpg"));
+        assert_eq!(config.output.art, PathBuf::from("../out/art.png"));
+        assert_eq!(config.output.svg, Some(PathBuf::from("../../tmp/art.svg")));
+
+        config.resolve_paths(Path::new("/work/jobs"));
+        assert_eq!(normalize(&config.input), PathBuf::from("/work/pikachu.jpg"));
+    }
+
+    #[test]
+    fn test_nails_too_close() {
+        let job = JOB
+            .replace("nail_count = 200", "nail_count = 400")
+            .replace("diameter_mm = 500.0", "diameter_mm = 50.0")
+            .replace("pixels_per_mm = 0.4", "pixels_per_mm = 1.0");
+        let config = Config::from_toml(&job).unwrap();
+        assert_eq!(config.board.diameter_mm, Some(50.0));
+
+        let err = config
+            .build_board(&DynamicImage::new_rgb8(1, 1))
+            .unwrap_err();
+        assert!(err.contains("less than a pixel apart"));
+    }
+
+    #[test]
+    fn test_too_many_colors() {
+        let job = JOB.replace(
+            r##"colors = ["d6babd", "#6b607a", "140917"]"##,
+            "extract = 300",
+        );
+        assert!(Config::from_toml(&job).is_err());
+
+        let blend = job.replace("[stop]", "[algorithm]\nkind = \"blend\"\n[stop]");
+        assert!(Config::from_toml(&blend).is_ok());
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use image::{
+    gif::{GifDecoder, GifEncoder, Repeat},
+    AnimationDecoder, Delay, DynamicImage, Frame, Rgb, Rgba, RgbaImage,
+};
+
+/// GIF colour quantization speed, from 1 (best) to 30 (fastest).
+const GIF_SPEED: i32 = 10;
+
+/// Records the art while it is being strung, as an animated GIF or as a
+/// sequence of numbered PNG frames.
+pub struct BuildAnimation {
+    output: Output,
+    background: Rgb<u8>,
+    frame_delay_ms: u32,
+    frame_count: usize,
+}
+
+enum Output {
+    /// The encoder and the file it writes to, shared so frames can be flushed
+    /// to disk without finishing the GIF
+    Gif(Box<GifEncoder<SharedFile>>, SharedFile),
+    /// Directory and file name stem of the frames
+    Frames(PathBuf, String),
+}
+
+#[derive(Clone)]
+struct SharedFile(Rc<RefCell<BufWriter<File>>>);
+
+impl Write for SharedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl BuildAnimation {
+    /// Start an animation at `path`. A `.gif` path gets an animated GIF that
+    /// loops forever, a `.png` path gets one `<name>-<frame>.png` file per
+    /// frame next to it. Frames show the art on a board of color `background`.
+    pub fn create(
+        path: &Path,
+        background: Rgb<u8>,
+        frame_delay_ms: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let output = match extension(path).as_deref() {
+            Some("gif") => {
+                let file = SharedFile(Rc::new(RefCell::new(BufWriter::new(File::create(path)?))));
+                let mut encoder = GifEncoder::new_with_speed(file.clone(), GIF_SPEED);
+                encoder.set_repeat(Repeat::Infinite)?;
+                Output::Gif(Box::new(encoder), file)
+            }
+            Some("png") => {
+                let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                let stem = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or("frame")
+                    .to_string();
+                Output::Frames(dir, stem)
+            }
+            _ => return Err(format!("{} is neither .gif nor .png", path.display()).into()),
+        };
+
+        Ok(Self {
+            output,
+            background,
+            frame_delay_ms,
+            frame_count: 0,
+        })
+    }
+
+    /// Carry on with an animation at `path` that already has `frame_count`
+    /// frames, as when resuming a run. A GIF keeps its first `frame_count`
+    /// frames, or as many of them as can still be read if the run stopped
+    /// before they were all written, numbered PNG frames carry on counting
+    /// from there.
+    pub fn resume(
+        path: &Path,
+        background: Rgb<u8>,
+        frame_delay_ms: u32,
+        frame_count: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        // read the frames so far before creating the file writes over them
+        let frames = if extension(path).as_deref() == Some("gif") && frame_count > 0 {
+            // a GIF that was never finished ends in a broken frame or none
+            let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+            let frames = decoder
+                .into_frames()
+                .take(frame_count)
+                .map_while(Result::ok)
+                .collect::<Vec<_>>();
+            if frames.len() < frame_count {
+                println!(
+                    "{} has {} of {} frames, carrying on from frame {}",
+                    path.display(),
+                    frames.len(),
+                    frame_count,
+                    frames.len()
+                );
+            }
+            frames
+        } else {
+            Vec::new()
+        };
+
+        let mut animation = BuildAnimation::create(path, background, frame_delay_ms)?;
+        animation.frame_count = match &mut animation.output {
+            Output::Gif(encoder, _) => {
+                let count = frames.len();
+                encoder.encode_frames(frames)?;
+                count
+            }
+            Output::Frames(..) => frame_count,
+        };
+
+        Ok(animation)
+    }
+
+    pub fn add_frame(&mut self, art: &DynamicImage) -> Result<(), Box<dyn std::error::Error>> {
+        let frame = on_background(art, self.background);
+        self.frame_count += 1;
+
+        match &mut self.output {
+            Output::Gif(encoder, _) => {
+                let delay = Delay::from_numer_denom_ms(self.frame_delay_ms, 1);
+                encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))?;
+            }
+            Output::Frames(dir, stem) => {
+                frame.save(dir.join(format!("{}-{:04}.png", stem, self.frame_count)))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Write the frames so far to disk, so a checkpoint's frame count holds
+    /// even if the run stops before the animation is finished.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            Output::Gif(_, file) => file.flush(),
+            Output::Frames(..) => Ok(()),
+        }
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+}
+
+fn on_background(art: &DynamicImage, background: Rgb<u8>) -> RgbaImage {
+    let mut frame = art.to_rgba8();
+
+    for pixel in frame.pixels_mut() {
+        let Rgba([r, g, b, a]) = *pixel;
+        let alpha = a as f32 / 255.0;
+        let mix = |c: u8, bg: u8| (c as f32 * alpha + bg as f32 * (1.0 - alpha)).round() as u8;
+        let Rgb([bg_r, bg_g, bg_b]) = background;
+
+        *pixel = Rgba([mix(r, bg_r), mix(g, bg_g), mix(b, bg_b), 255]);
+    }
+
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{gif::GifDecoder, AnimationDecoder};
+
+    fn frames() -> Vec<DynamicImage> {
+        (0..3)
+            .map(|i| {
+                DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, _| {
+                    if x < i {
+                        Rgba([255, 0, 0, 255])
+                    } else {
+                        Rgba([0, 0, 0, 0])
+                    }
+                }))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_on_background() {
+        let frame = on_background(&frames()[1], Rgb([0, 0, 255]));
+
+        assert_eq!(*frame.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*frame.get_pixel(3, 0), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_gif_and_frames() {
+        let dir = std::env::temp_dir().join(format!("stringify-animation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let gif = dir.join("build.gif");
+        let mut animation = BuildAnimation::create(&gif, Rgb([255, 255, 255]), 100).unwrap();
+        for frame in frames() {
+            animation.add_frame(&frame).unwrap();
+        }
+        assert_eq!(animation.frame_count(), 3);
+        drop(animation);
+
+        let decoder = GifDecoder::new(File::open(&gif).unwrap()).unwrap();
+        assert_eq!(decoder.into_frames().count(), 3);
+
+        let mut animation =
+            BuildAnimation::create(&dir.join("step.png"), Rgb([255, 255, 255]), 100).unwrap();
+        for frame in frames() {
+            animation.add_frame(&frame).unwrap();
+        }
+        assert!(dir.join("step-0003.png").exists());
+
+        // resuming keeps the frames before the checkpoint and drops later ones
+        let mut animation = BuildAnimation::resume(&gif, Rgb([255, 255, 255]), 100, 2).unwrap();
+        animation.add_frame(&frames()[0]).unwrap();
+        assert_eq!(animation.frame_count(), 3);
+        drop(animation);
+        let decoder = GifDecoder::new(File::open(&gif).unwrap()).unwrap();
+        let resumed = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(resumed.len(), 3);
+        assert_eq!(resumed[1].buffer().get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(
+            resumed[2].buffer().get_pixel(0, 0),
+            &Rgba([255, 255, 255, 255])
+        );
+        // a GIF with fewer frames than expected carries on from the ones it has
+        let animation = BuildAnimation::resume(&gif, Rgb([0, 0, 0]), 100, 5).unwrap();
+        assert_eq!(animation.frame_count(), 3);
+        drop(animation);
+
+        let mut animation =
+            BuildAnimation::resume(&dir.join("step.png"), Rgb([255, 255, 255]), 100, 3).unwrap();
+        animation.add_frame(&frames()[0]).unwrap();
+        assert!(dir.join("step-0004.png").exists());
+
+        assert!(BuildAnimation::create(&dir.join("build.mp4"), Rgb([0, 0, 0]), 100).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_resume_after_crash() {
+        let dir = std::env::temp_dir().join(format!("stringify-crash-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let gif = dir.join("build.gif");
+
+        // flushed for a checkpoint after 2 frames, then killed before the
+        // GIF is finished
+        let mut animation = BuildAnimation::create(&gif, Rgb([255, 255, 255]), 100).unwrap();
+        for frame in &frames()[..2] {
+            animation.add_frame(frame).unwrap();
+        }
+        animation.flush().unwrap();
+        let checkpoint_frames = animation.frame_count();
+        animation.add_frame(&frames()[2]).unwrap();
+        std::mem::forget(animation);
+
+        let mut animation =
+            BuildAnimation::resume(&gif, Rgb([255, 255, 255]), 100, checkpoint_frames).unwrap();
+        assert_eq!(animation.frame_count(), 2);
+        animation.add_frame(&frames()[2]).unwrap();
+        drop(animation);
+
+        let decoder = GifDecoder::new(File::open(&gif).unwrap()).unwrap();
+        let resumed = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(resumed.len(), 3);
+        assert_eq!(resumed[1].buffer().get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
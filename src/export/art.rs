@@ -0,0 +1,131 @@
+use std::{fmt::Write, path::Path};
+
+use image::Rgb;
+
+use crate::{
+    art_generator::{segments, NailPattern},
+    board::Board,
+    util::to_hex_color,
+};
+
+/// Thread width used for drawing when the board doesn't set one.
+pub const DEFAULT_THREAD_WIDTH_MM: f64 = 0.5;
+
+/// How threads look when drawing a finished pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThreadStyle {
+    pub thread_width_mm: f64,
+    /// How much of what is below a thread it hides, `1.0` for solid thread
+    pub opacity: f32,
+    /// Board color, transparent when `None`
+    pub background: Option<Rgb<u8>>,
+}
+
+impl ThreadStyle {
+    /// Solid thread as wide as the board's thread, or
+    /// [`DEFAULT_THREAD_WIDTH_MM`] if it has none, on a transparent board.
+    pub fn for_board(board: &Board) -> Self {
+        let thread_width_mm = if board.thread_width_mm() > 0.0 {
+            board.thread_width_mm()
+        } else {
+            DEFAULT_THREAD_WIDTH_MM
+        };
+
+        Self {
+            thread_width_mm,
+            opacity: 1.0,
+            background: None,
+        }
+    }
+}
+
+/// Every segment of `pattern` as a straight line drawn to scale in
+/// millimetres, in the order the threads were laid. Lines run between the
+/// points where the thread touches the nails, the nail centres unless the
+/// board has a nail diameter.
+pub fn art_svg(board: &Board, pattern: &NailPattern, style: &ThreadStyle) -> String {
+    let (width, height) = (board.width_mm(), board.height_mm());
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}mm\" height=\"{1}mm\" viewBox=\"0 0 {0} {1}\">\n",
+        width, height
+    );
+    if let Some(background) = style.background {
+        writeln!(
+            svg,
+            r##"<rect width="{}" height="{}" fill="#{}"/>"##,
+            width,
+            height,
+            to_hex_color(&background)
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        svg,
+        r#"<g fill="none" stroke-width="{}" stroke-opacity="{}" stroke-linecap="round">"#,
+        style.thread_width_mm, style.opacity
+    )
+    .unwrap();
+
+    for (color, from, to) in segments(pattern) {
+        let start = board.attachment_point_mm(from, to);
+        let end = board.attachment_point_mm(to, from);
+
+        writeln!(
+            svg,
+            r##"<line x1="{:.3}" y1="{:.3}" x2="{:.3}" y2="{:.3}" stroke="#{}"/>"##,
+            start.0,
+            start.1,
+            end.0,
+            end.1,
+            to_hex_color(&color)
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+pub fn save_art_svg(
+    board: &Board,
+    pattern: &NailPattern,
+    style: &ThreadStyle,
+    path: &Path,
+) -> std::io::Result<()> {
+    std::fs::write(path, art_svg(board, pattern, style))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_art_svg() {
+        let board = Board::new_rectangle(100.0, 50.0, 4, 1.0).unwrap();
+        let [a, b, c, _] = [0, 1, 2, 3].map(|i| board.nails()[i]);
+        let (red, black) = (Rgb([255, 0, 0]), Rgb([0, 0, 0]));
+        let pattern = vec![(red, a), (black, c), (red, b), (black, a), (red, c)];
+
+        assert_eq!(
+            segments(&pattern).collect::<Vec<_>>(),
+            vec![(red, a, b), (black, c, a), (red, b, c)]
+        );
+
+        let style = ThreadStyle {
+            opacity: 0.5,
+            background: Some(Rgb([255, 255, 255])),
+            ..ThreadStyle::for_board(&board)
+        };
+        let svg = art_svg(&board, &pattern, &style);
+
+        assert!(svg.contains(r#"width="100mm" height="50mm" viewBox="0 0 100 50""#));
+        assert!(svg.contains(r##"<rect width="100" height="50" fill="#ffffff"/>"##));
+        assert!(svg.contains(r#"stroke-width="0.5" stroke-opacity="0.5""#));
+        assert_eq!(svg.matches("<line").count(), 3);
+        assert!(svg.contains(
+            r##"<line x1="0.000" y1="0.000" x2="100.000" y2="0.000" stroke="#ff0000"/>"##
+        ));
+    }
+}
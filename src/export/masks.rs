@@ -0,0 +1,32 @@
+use std::{collections::HashMap, path::Path};
+
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageResult, Rgb};
+
+use crate::util::to_hex_color;
+
+/// Save one black and white image per palette color into `dir`, showing which
+/// pixels of the dithered image were assigned to that color.
+pub fn save_mask_images(
+    color_masks: &HashMap<Rgb<u8>, Vec<Vec<bool>>>,
+    dithered: &DynamicImage,
+    dir: &Path,
+) -> ImageResult<()> {
+    for (color, mask) in color_masks {
+        let mut img = ImageBuffer::new(dithered.width(), dithered.height());
+        for y in 0..dithered.height() {
+            for x in 0..dithered.width() {
+                let pixel_color = if mask[x as usize][y as usize] {
+                    *color
+                } else if *color == Rgb([255, 255, 255]) {
+                    Rgb([0, 0, 0])
+                } else {
+                    Rgb([255, 255, 255])
+                };
+                img.put_pixel(x, y, pixel_color);
+            }
+        }
+        img.save(dir.join(format!("mask_{}.png", to_hex_color(color))))?;
+    }
+
+    Ok(())
+}
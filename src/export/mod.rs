@@ -0,0 +1,13 @@
+mod animation;
+mod art;
+mod masks;
+mod pattern;
+mod preview;
+mod template;
+
+pub use animation::*;
+pub use art::*;
+pub use masks::*;
+pub use pattern::*;
+pub use preview::*;
+pub use template::*;
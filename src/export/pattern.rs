@@ -0,0 +1,257 @@
+use std::{fmt::Write, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    art_generator::{pattern_steps, NailPattern},
+    board::{Board, Wrap},
+    util::to_hex_color,
+};
+
+/// Everything needed to build a finished piece by hand: where the nails go
+/// and, for every thread color, the nails to wind it around in order.
+///
+/// Nails are referred to by their index in [`Board::nails`], not by pixel.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildInstructions {
+    pub board: BoardInfo,
+    pub threads: Vec<ThreadInstructions>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BoardInfo {
+    pub width_mm: f64,
+    pub height_mm: f64,
+    pub nail_count: usize,
+    pub nail_diameter_mm: f64,
+    pub thread_width_mm: f64,
+    /// Position of every nail in millimetres from the top left corner, by index
+    pub nails_mm: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ThreadInstructions {
+    /// Hex color of the thread, without a leading `#`
+    pub color: String,
+    pub length_mm: f64,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Step {
+    /// Position in the whole pattern, counting every color, so threads can be
+    /// laid down in the same order they were generated
+    pub step: usize,
+    pub nail: usize,
+    /// Which way to go around the nail, `None` where the thread is tied on or
+    /// the board can't tell, see [`Board::wrap`]
+    pub wrap: Option<Wrap>,
+}
+
+impl BuildInstructions {
+    pub fn new(board: &Board, pattern: &NailPattern) -> Self {
+        let board_info = BoardInfo {
+            width_mm: board.width_mm(),
+            height_mm: board.height_mm(),
+            nail_count: board.nails().len(),
+            nail_diameter_mm: board.nail_diameter_mm(),
+            thread_width_mm: board.thread_width_mm(),
+            nails_mm: board
+                .nail_positions_mm()
+                .iter()
+                .map(|position| (position.0, position.1))
+                .collect(),
+        };
+
+        let mut threads: Vec<ThreadInstructions> = Vec::new();
+        for (step, (color, last_nail, nail)) in pattern_steps(pattern).enumerate() {
+            let name = to_hex_color(&color);
+
+            let thread = match threads.iter().position(|thread| thread.color == name) {
+                Some(i) => &mut threads[i],
+                None => {
+                    threads.push(ThreadInstructions {
+                        color: name,
+                        length_mm: 0.0,
+                        steps: Vec::new(),
+                    });
+                    threads.last_mut().unwrap()
+                }
+            };
+
+            if let Some(last_nail) = last_nail {
+                thread.length_mm += board.distance_mm(last_nail, nail);
+            }
+            thread.steps.push(Step {
+                step,
+                nail: board.nail_index(nail).expect("nail is not on this board"),
+                wrap: last_nail.and_then(|last_nail| board.wrap(last_nail, nail)),
+            });
+        }
+
+        Self {
+            board: board_info,
+            threads,
+        }
+    }
+
+    /// Save the instructions, in CSV, JSON or plain text depending on whether
+    /// `path` ends in `.csv`, `.json` or `.txt`.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let text = match extension.as_deref() {
+            Some("csv") => self.to_csv(),
+            Some("json") => self.to_json()?,
+            Some("txt") => self.to_text(),
+            _ => return Err(format!("{} is not .csv, .json or .txt", path.display()).into()),
+        };
+
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// One `step,color,nail,wrap` row per nail, grouped by color. The board
+    /// and nail positions come first as `#` comments.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+
+        for line in self.board_summary() {
+            writeln!(csv, "# {}", line).unwrap();
+        }
+        for (i, (x, y)) in self.board.nails_mm.iter().enumerate() {
+            writeln!(csv, "# nail {}: {:.2},{:.2}", i, x, y).unwrap();
+        }
+
+        writeln!(csv, "step,color,nail,wrap").unwrap();
+        for thread in &self.threads {
+            for step in &thread.steps {
+                let wrap = match step.wrap {
+                    Some(Wrap::Clockwise) => "clockwise",
+                    Some(Wrap::CounterClockwise) => "counter_clockwise",
+                    None => "",
+                };
+                writeln!(csv, "{},{},{},{}", step.step, thread.color, step.nail, wrap).unwrap();
+            }
+        }
+
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Numbered steps for every thread, meant to be followed with the board
+    /// in front of you.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for line in self.board_summary() {
+            writeln!(text, "{}", line).unwrap();
+        }
+        writeln!(
+            text,
+            "Nails are numbered from 0 in the order they were placed."
+        )
+        .unwrap();
+
+        for (i, thread) in self.threads.iter().enumerate() {
+            writeln!(
+                text,
+                "\nThread {} of {}: #{}, {:.2} m",
+                i + 1,
+                self.threads.len(),
+                thread.color,
+                thread.length_mm / 1000.0
+            )
+            .unwrap();
+
+            for (n, step) in thread.steps.iter().enumerate() {
+                let action = match step.wrap {
+                    None if n == 0 => "tie on at",
+                    None => "around",
+                    Some(Wrap::Clockwise) => "clockwise around",
+                    Some(Wrap::CounterClockwise) => "counter-clockwise around",
+                };
+                writeln!(text, "{:>6}. {} nail {}", n + 1, action, step.nail).unwrap();
+            }
+        }
+
+        text
+    }
+
+    fn board_summary(&self) -> Vec<String> {
+        let board = &self.board;
+        let mut lines = vec![
+            format!("board: {:.1} x {:.1} mm", board.width_mm, board.height_mm),
+            format!("nails: {}", board.nail_count),
+        ];
+        if board.nail_diameter_mm > 0.0 {
+            lines.push(format!("nail diameter: {:.2} mm", board.nail_diameter_mm));
+        }
+        if board.thread_width_mm > 0.0 {
+            lines.push(format!("thread width: {:.2} mm", board.thread_width_mm));
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    // two threads on a square board with a nail in every corner
+    fn instructions() -> BuildInstructions {
+        let board = Board::new_rectangle(100.0, 100.0, 4, 1.0).unwrap();
+        let [a, b, c, _] = [0, 1, 2, 3].map(|i| board.nails()[i]);
+        let red = Rgb([255, 0, 0]);
+        let black = Rgb([0, 0, 0]);
+
+        let pattern = vec![(red, a), (black, b), (red, c), (black, a), (red, b)];
+        BuildInstructions::new(&board, &pattern)
+    }
+
+    #[test]
+    fn test_grouped_by_color() {
+        let instructions = instructions();
+        let [red, black] = [&instructions.threads[0], &instructions.threads[1]];
+
+        assert_eq!(instructions.board.nail_count, 4);
+        assert_eq!(red.color, "ff0000");
+        assert_eq!(
+            red.steps
+                .iter()
+                .map(|s| (s.step, s.nail))
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (2, 2), (4, 1)]
+        );
+        assert_eq!(red.steps[0].wrap, None);
+        assert!(red.steps[1].wrap.is_some());
+        assert_eq!(black.steps.len(), 2);
+        assert!((black.length_mm - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_formats() {
+        let instructions = instructions();
+
+        let csv = instructions.to_csv();
+        assert!(csv.contains("\nstep,color,nail,wrap\n0,ff0000,0,\n2,ff0000,2,"));
+        assert!(csv.contains("# nail 2: 100.00,100.00"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&instructions.to_json().unwrap()).unwrap();
+        assert_eq!(json["threads"][1]["steps"][1]["nail"], 0);
+        assert_eq!(json["board"]["nails_mm"][1][0], 100.0);
+
+        let text = instructions.to_text();
+        assert!(text.contains("Thread 2 of 2: #000000, 0.10 m"));
+        assert!(text.contains("     1. tie on at nail 1"));
+    }
+}
@@ -0,0 +1,182 @@
+use image::{imageops::FilterType, DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    art_generator::{segments, NailPattern},
+    board::{Board, NailPosition},
+    outline, raster,
+    util::Dimensions,
+};
+
+use super::ThreadStyle;
+
+const NAIL_COLOR: [f32; 3] = [0.55, 0.55, 0.58];
+/// Nails without a diameter are still drawn this wide.
+const MIN_NAIL_DIAMETER_MM: f64 = 1.0;
+
+/// Premultiplied RGBA
+type Pixel = [f32; 4];
+
+/// Render `pattern` the way the finished board will look, at `pixels_per_mm`
+/// rather than the board's working resolution.
+///
+/// Threads are drawn anti-aliased at their real width, each laid translucently
+/// over the ones before it. The board shows `texture`, scaled to cover it, or
+/// else the style's background. Nails are drawn on top of the threads and
+/// anything off a shaped board is left transparent.
+pub fn render_preview(
+    board: &Board,
+    pattern: &NailPattern,
+    style: &ThreadStyle,
+    pixels_per_mm: f64,
+    texture: Option<&DynamicImage>,
+) -> RgbaImage {
+    assert!(pixels_per_mm > 0.0);
+
+    let dimensions = Dimensions::new(
+        (board.width_mm() * pixels_per_mm).ceil().max(1.0) as u32,
+        (board.height_mm() * pixels_per_mm).ceil().max(1.0) as u32,
+    );
+    let (width, height) = (dimensions.width(), dimensions.height());
+    let to_pixels = |NailPosition(x, y): NailPosition| (x * pixels_per_mm, y * pixels_per_mm);
+
+    let mut canvas: Vec<Pixel> = match (texture, style.background) {
+        (Some(texture), _) => texture
+            .resize_to_fill(width, height, FilterType::Triangle)
+            .to_rgba8()
+            .pixels()
+            .map(|Rgba([r, g, b, _])| [*r, *g, *b, 255].map(|c| c as f32 / 255.0))
+            .collect(),
+        (None, Some(background)) => {
+            let [r, g, b] = background.0.map(|c| c as f32 / 255.0);
+            vec![[r, g, b, 1.0]; (width * height) as usize]
+        }
+        (None, None) => vec![[0.0; 4]; (width * height) as usize],
+    };
+
+    let thread_width = style.thread_width_mm * pixels_per_mm;
+
+    for (color, from, to) in segments(pattern) {
+        let start = to_pixels(board.attachment_point_mm(from, to));
+        let end = to_pixels(board.attachment_point_mm(to, from));
+        let color = color.0.map(|c| c as f32 / 255.0);
+
+        for (x, y, coverage) in raster::thread_coverage(start, end, thread_width, &dimensions) {
+            let pixel = &mut canvas[(y * width + x) as usize];
+            over(pixel, color, coverage * style.opacity);
+        }
+    }
+
+    let nail_radius = board.nail_diameter_mm().max(MIN_NAIL_DIAMETER_MM) / 2.0 * pixels_per_mm;
+
+    for position in board.nail_positions_mm() {
+        let (cx, cy) = to_pixels(*position);
+        let reach = nail_radius + 1.0;
+        let (x0, x1) = (
+            (cx - reach).max(0.0) as u32,
+            ((cx + reach).ceil() as u32).min(width),
+        );
+        let (y0, y1) = (
+            (cy - reach).max(0.0) as u32,
+            ((cy + reach).ceil() as u32).min(height),
+        );
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let distance = (x as f64 + 0.5 - cx).hypot(y as f64 + 0.5 - cy);
+                let coverage = (nail_radius - distance + 0.5).clamp(0.0, 1.0) as f32;
+                if coverage > 0.0 {
+                    over(&mut canvas[(y * width + x) as usize], NAIL_COLOR, coverage);
+                }
+            }
+        }
+    }
+
+    // cut at the preview's resolution, the board's mask is too coarse
+    let mask = board
+        .outline_mm()
+        .map(|outline| outline::mask(outline, pixels_per_mm, &dimensions));
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let i = (y * width + x) as usize;
+        let on_board = mask.as_ref().is_none_or(|mask| mask[i]);
+
+        let [r, g, b, a] = canvas[i];
+        if !on_board || a <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+
+        let channel = |c: f32| (c / a * 255.0).round().clamp(0.0, 255.0) as u8;
+        Rgba([
+            channel(r),
+            channel(g),
+            channel(b),
+            (a * 255.0).round() as u8,
+        ])
+    })
+}
+
+/// Lay `color` over `pixel`, hiding `alpha` of it.
+fn over(pixel: &mut Pixel, color: [f32; 3], alpha: f32) {
+    for c in 0..3 {
+        pixel[c] = color[c] * alpha + pixel[c] * (1.0 - alpha);
+    }
+    pixel[3] = alpha + pixel[3] * (1.0 - alpha);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_render_preview() {
+        let board = Board::new_rectangle(20.0, 20.0, 4, 1.0).unwrap();
+        let [a, _, c, _] = [0, 1, 2, 3].map(|i| board.nails()[i]);
+        let red = Rgb([255, 0, 0]);
+        let pattern = vec![(red, a), (red, c)];
+
+        let style = ThreadStyle {
+            thread_width_mm: 1.0,
+            opacity: 0.5,
+            background: Some(Rgb([255, 255, 255])),
+        };
+        let preview = render_preview(&board, &pattern, &style, 10.0, None);
+
+        assert_eq!(preview.dimensions(), (200, 200));
+        // half way along the diagonal, half red over white
+        assert_eq!(*preview.get_pixel(100, 100), Rgba([255, 128, 128, 255]));
+        assert_eq!(*preview.get_pixel(150, 50), Rgba([255, 255, 255, 255]));
+        // the nail in the top right corner
+        assert_eq!(*preview.get_pixel(199, 0), Rgba([140, 140, 148, 255]));
+    }
+
+    #[test]
+    fn test_shaped_board_is_cut_out() {
+        let triangle = vec![(0.0, 0.0), (20.0, 0.0), (0.0, 20.0)];
+        let board = Board::from_outline(&triangle, 6, 1.0).unwrap();
+        let style = ThreadStyle {
+            background: Some(Rgb([200, 180, 150])),
+            ..ThreadStyle::for_board(&board)
+        };
+
+        let preview = render_preview(&board, &Vec::new(), &style, 4.0, None);
+
+        assert_eq!(*preview.get_pixel(10, 10), Rgba([200, 180, 150, 255]));
+        assert_eq!(preview.get_pixel(70, 70).0[3], 0);
+
+        // the cut follows the diagonal edge pixel by pixel, not the 1mm
+        // pixels of the board
+        for (x, y) in [(40, 38), (37, 41), (41, 39), (39, 40)] {
+            let centre = ((x as f64 + 0.5) / 4.0, (y as f64 + 0.5) / 4.0);
+            let on_board = outline::contains(&triangle, centre);
+            assert_eq!(
+                preview.get_pixel(x, y).0[3] == 255,
+                on_board,
+                "{}, {}",
+                x,
+                y
+            );
+        }
+        assert_eq!(preview.get_pixel(40, 38).0[3], 255);
+    }
+}
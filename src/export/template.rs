@@ -0,0 +1,329 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::board::{Board, NailPosition};
+
+/// Blank border around the printed part of every page, most printers can't
+/// print right up to the edge.
+const MARGIN_MM: f64 = 10.0;
+/// Size of the cross marking the centre of a nail.
+const CROSS_MM: f64 = 2.0;
+const FONT_MM: f64 = 2.5;
+
+/// Paper to tile a nail template across.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paper {
+    A4,
+    Letter,
+}
+
+impl Paper {
+    /// Portrait width and height in millimetres.
+    pub fn size_mm(&self) -> (f64, f64) {
+        match self {
+            Paper::A4 => (210.0, 297.0),
+            Paper::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+/// A 1:1 template of the board to drill or hammer nails through, as a single
+/// SVG as large as the board. Every nail gets a cross on its centre, a circle
+/// as large as the nail and its index from [`Board::nails`].
+pub fn template_svg(board: &Board) -> String {
+    let (width, height) = (board.width_mm(), board.height_mm());
+
+    let mut svg = svg_start(width + 2.0 * MARGIN_MM, height + 2.0 * MARGIN_MM);
+    writeln!(svg, r#"<g transform="translate({0} {0})">"#, MARGIN_MM).unwrap();
+    draw_board(&mut svg, board);
+    svg.push_str("</g>\n</svg>\n");
+
+    svg
+}
+
+/// The template split into pages of `paper`. Each page shows one tile of the
+/// board inside a dashed trim line; cut along it and butt the pages together
+/// so the half circles of the alignment marks on their edges meet. Pages go
+/// row by row from the top left of the board.
+pub fn template_pages(board: &Board, paper: Paper) -> Vec<String> {
+    let (page_width, page_height) = paper.size_mm();
+    let (tile_width, tile_height) = (page_width - 2.0 * MARGIN_MM, page_height - 2.0 * MARGIN_MM);
+
+    // leave room around the board for the labels of the outer nails
+    let (width, height) = (
+        board.width_mm() + 2.0 * MARGIN_MM,
+        board.height_mm() + 2.0 * MARGIN_MM,
+    );
+    let columns = (width / tile_width).ceil().max(1.0) as usize;
+    let rows = (height / tile_height).ceil().max(1.0) as usize;
+
+    let mut pages = Vec::with_capacity(rows * columns);
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let (left, top) = (column as f64 * tile_width, row as f64 * tile_height);
+
+            let mut svg = svg_start(page_width, page_height);
+            writeln!(
+                svg,
+                r#"<defs><clipPath id="tile"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath></defs>"#,
+                MARGIN_MM, MARGIN_MM, tile_width, tile_height
+            )
+            .unwrap();
+
+            // the board, shifted so this tile lands inside the trim line
+            writeln!(
+                svg,
+                r#"<g clip-path="url(#tile)"><g transform="translate({} {})">"#,
+                2.0 * MARGIN_MM - left,
+                2.0 * MARGIN_MM - top
+            )
+            .unwrap();
+            draw_board(&mut svg, board);
+            svg.push_str("</g></g>\n");
+
+            writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="gray" stroke-width="0.2" stroke-dasharray="2 2"/>"#,
+                MARGIN_MM, MARGIN_MM, tile_width, tile_height
+            )
+            .unwrap();
+            for (x, y) in [
+                (MARGIN_MM, MARGIN_MM),
+                (MARGIN_MM + tile_width, MARGIN_MM),
+                (MARGIN_MM, MARGIN_MM + tile_height),
+                (MARGIN_MM + tile_width, MARGIN_MM + tile_height),
+            ] {
+                draw_alignment_mark(&mut svg, x, y);
+            }
+
+            writeln!(
+                svg,
+                r#"<text x="{}" y="{}" font-size="{}" font-family="sans-serif">page {} of {}, row {} column {}. Print at 100% scale.</text>"#,
+                MARGIN_MM,
+                MARGIN_MM / 2.0,
+                FONT_MM * 1.5,
+                pages.len() + 1,
+                rows * columns,
+                row + 1,
+                column + 1
+            )
+            .unwrap();
+            if pages.is_empty() {
+                draw_scale(&mut svg, MARGIN_MM, page_height - MARGIN_MM / 2.0);
+            }
+
+            svg.push_str("</svg>\n");
+            pages.push(svg);
+        }
+    }
+
+    pages
+}
+
+/// Save the template to `path`, tiled across pages of `paper` if given. A
+/// template that needs more than one page is saved as `<name>-<page>.svg`
+/// next to `path`. Returns the files written.
+pub fn save_template(
+    board: &Board,
+    paper: Option<Paper>,
+    path: &Path,
+) -> std::io::Result<Vec<PathBuf>> {
+    let pages = match paper {
+        Some(paper) => template_pages(board, paper),
+        None => vec![template_svg(board)],
+    };
+
+    if pages.len() == 1 {
+        std::fs::write(path, &pages[0])?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("template");
+    let mut paths = Vec::with_capacity(pages.len());
+
+    for (i, page) in pages.iter().enumerate() {
+        let page_path = path.with_file_name(format!("{}-{:02}.svg", stem, i + 1));
+        std::fs::write(&page_path, page)?;
+        paths.push(page_path);
+    }
+
+    Ok(paths)
+}
+
+fn svg_start(width_mm: f64, height_mm: f64) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}mm\" height=\"{1}mm\" viewBox=\"0 0 {0} {1}\">\n",
+        width_mm, height_mm
+    )
+}
+
+/// The board edge, its centre and every nail, in board millimetres.
+fn draw_board(svg: &mut String, board: &Board) {
+    let (width, height) = (board.width_mm(), board.height_mm());
+    let centre = (width / 2.0, height / 2.0);
+
+    let style = r#"fill="none" stroke="lightgray" stroke-width="0.2""#;
+    match board.outline_mm() {
+        Some(outline) => {
+            let points = outline
+                .iter()
+                .map(|(x, y)| format!("{},{}", x, y))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(svg, r#"<polygon points="{}" {}/>"#, points, style).unwrap();
+        }
+        None if board.is_circle() => {
+            writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" {}/>"#,
+                centre.0,
+                centre.1,
+                width / 2.0,
+                style
+            )
+            .unwrap();
+        }
+        None => {
+            writeln!(
+                svg,
+                r#"<rect width="{}" height="{}" {}/>"#,
+                width, height, style
+            )
+            .unwrap();
+        }
+    }
+    draw_cross(svg, centre.0, centre.1, 3.0 * CROSS_MM, "gray");
+
+    let radius = (board.nail_diameter_mm() / 2.0).max(0.5);
+
+    for (i, NailPosition(x, y)) in board.nail_positions_mm().iter().enumerate() {
+        writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="none" stroke="black" stroke-width="0.15"/>"#,
+            x, y, radius
+        )
+        .unwrap();
+        draw_cross(svg, *x, *y, CROSS_MM, "black");
+
+        // labels sit outside the nail, away from the centre of the board
+        let (dx, dy) = (x - centre.0, y - centre.1);
+        let length = dx.hypot(dy);
+        let (dx, dy) = if length < f64::EPSILON {
+            (1.0, 0.0)
+        } else {
+            (dx / length, dy / length)
+        };
+        let offset = radius + CROSS_MM + FONT_MM / 2.0;
+
+        writeln!(
+            svg,
+            r#"<text x="{:.3}" y="{:.3}" font-size="{}" font-family="sans-serif" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+            x + dx * offset,
+            y + dy * offset,
+            FONT_MM,
+            i
+        )
+        .unwrap();
+    }
+}
+
+fn draw_cross(svg: &mut String, x: f64, y: f64, size: f64, color: &str) {
+    let half = size / 2.0;
+    writeln!(
+        svg,
+        r#"<path d="M{} {}h{}M{} {}v{}" stroke="{}" stroke-width="0.1"/>"#,
+        x - half,
+        y,
+        size,
+        x,
+        y - half,
+        size,
+        color
+    )
+    .unwrap();
+}
+
+fn draw_alignment_mark(svg: &mut String, x: f64, y: f64) {
+    writeln!(
+        svg,
+        r#"<circle cx="{}" cy="{}" r="3" fill="none" stroke="black" stroke-width="0.2"/>"#,
+        x, y
+    )
+    .unwrap();
+    draw_cross(svg, x, y, 8.0, "black");
+}
+
+/// A 50mm bar to check the page was printed at the right scale.
+fn draw_scale(svg: &mut String, x: f64, y: f64) {
+    writeln!(
+        svg,
+        r#"<path d="M{0} {1}h50M{0} {2}v2M{3} {2}v2" stroke="black" stroke-width="0.3"/><text x="{4}" y="{2}" font-size="{5}" font-family="sans-serif">50 mm</text>"#,
+        x,
+        y,
+        y - 1.0,
+        x + 50.0,
+        x + 52.0,
+        FONT_MM
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_sheet() {
+        let board = Board::new(100.0, 12, 1.0)
+            .unwrap()
+            .with_nail_diameter(2.0)
+            .unwrap();
+        let svg = template_svg(&board);
+
+        assert!(svg.starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="120mm" height="120mm""#
+        ));
+        // the nails and the edge of the board
+        assert_eq!(svg.matches("<circle").count(), 13);
+        assert!(svg.contains(r#"<circle cx="50" cy="50" r="50" fill="none""#));
+        assert!(svg.contains(">11</text>"));
+        assert!(svg.contains(r#"<circle cx="100" cy="50" r="1""#));
+        assert!(!svg.contains("<rect"));
+    }
+
+    #[test]
+    fn test_board_edges() {
+        let rectangle = Board::new_rectangle(60.0, 40.0, 8, 1.0).unwrap();
+        let svg = template_svg(&rectangle);
+        assert!(svg.contains(r#"<rect width="60" height="40" fill="none""#));
+        assert!(!svg.contains("<polygon"));
+
+        let triangle = [(10.0, 10.0), (50.0, 10.0), (30.0, 40.0)];
+        let svg = template_svg(&Board::from_outline(&triangle, 6, 1.0).unwrap());
+        assert!(svg.contains(r#"<polygon points="0,0 40,0 20,30" fill="none""#));
+        assert!(!svg.contains("<rect"));
+    }
+
+    #[test]
+    fn test_tiles() {
+        // 500mm plus margins needs 3 columns of 190mm and 2 rows of 277mm on A4
+        let board = Board::new(500.0, 100, 0.5).unwrap();
+        let pages = template_pages(&board, Paper::A4);
+
+        assert_eq!(pages.len(), 6);
+        assert!(pages[0].contains("page 1 of 6, row 1 column 1"));
+        assert!(pages[5].contains("page 6 of 6, row 2 column 3"));
+        assert!(pages[0].contains("50 mm"));
+        assert!(!pages[1].contains("50 mm"));
+        assert!(pages[4].contains(r#"translate(-170 -257)"#));
+
+        let small = Board::new(100.0, 12, 1.0).unwrap();
+        assert_eq!(template_pages(&small, Paper::Letter).len(), 1);
+    }
+}
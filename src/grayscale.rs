@@ -0,0 +1,303 @@
+use std::{collections::HashMap, sync::Arc};
+
+use image::{DynamicImage, Pixel, Rgb};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    art_algo::{ArtAlgo, StrandPositions},
+    board::{Board, Nail},
+    chords::Chords,
+};
+
+/// The classic single thread look: one dark thread on a light board, where
+/// every line adds a little darkness and overlapping lines build it up.
+///
+/// Keeps a residual buffer of the darkness still missing from every pixel.
+/// Each step runs the chord from the current nail that most reduces the
+/// squared residual, and subtracts `opacity` times the chord's coverage times
+/// the thread's own darkness along it, so a gray thread adds less than a black
+/// one and a white thread adds nothing. Stops once no chord improves the
+/// residual by more than `min_improvement`.
+pub struct Grayscale {
+    color: Rgb<u8>,
+    start: Option<Nail>,
+    nails: Vec<Nail>,
+    chords: Arc<Chords>,
+    residual: Vec<f32>,
+    opacity: f32,
+    /// How much darker than a white board the thread is, from 0 to 1
+    darkness: f32,
+    min_improvement: f32,
+    last_nail: Option<Nail>,
+}
+
+/// See [`ArtAlgo::save_state`].
+#[derive(Serialize, Deserialize)]
+struct GrayscaleState {
+    residual: Vec<f32>,
+    last_nail: Option<Nail>,
+}
+
+impl Grayscale {
+    pub fn new(
+        board: &Board,
+        src_img: &DynamicImage,
+        color: Rgb<u8>,
+        opacity: f32,
+        min_improvement: f32,
+    ) -> Self {
+        assert!(opacity > 0.0 && opacity <= 1.0);
+
+        let luma = board.scale_image(src_img, None).to_luma8();
+
+        let residual = luma
+            .enumerate_pixels()
+            .map(|(x, y, pixel)| {
+                if board.contains_pixel(x, y) {
+                    1.0 - pixel.0[0] as f32 / 255.0
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // pixels off a shaped board don't count either way
+        let chords = board.chords_on_board();
+
+        let mut grayscale = Self {
+            color,
+            start: None,
+            nails: board.nails().to_vec(),
+            chords,
+            residual,
+            opacity,
+            darkness: 1.0 - color.to_luma().0[0] as f32 / 255.0,
+            min_improvement,
+            last_nail: None,
+        };
+        grayscale.start = grayscale.starting_nail(board.nails());
+
+        grayscale
+    }
+
+    /// One end of the chord that improves the residual most.
+    fn starting_nail(&self, nails: &[Nail]) -> Option<Nail> {
+        let mut best_improvement = self.min_improvement;
+        let mut start = None;
+
+        for i in 0..nails.len() {
+            for j in i + 1..nails.len() {
+                let Some(improvement) = self.improvement(nails[i], nails[j]) else {
+                    continue;
+                };
+
+                if improvement > best_improvement {
+                    best_improvement = improvement;
+                    start = Some(nails[i]);
+                }
+            }
+        }
+
+        start
+    }
+
+    /// How much running a thread from `from` to `to` would reduce the squared
+    /// residual, `None` if there is no such chord.
+    fn improvement(&self, from: Nail, to: Nail) -> Option<f32> {
+        let chord = self.chords.get(from, to)?;
+
+        let improvement = chord
+            .iter()
+            .map(|(i, coverage)| {
+                let residual = self.residual[i];
+                let darkness = self.opacity * coverage * self.darkness;
+                residual * residual - (residual - darkness) * (residual - darkness)
+            })
+            .sum();
+
+        Some(improvement)
+    }
+
+    fn darken_path(&mut self, from: Nail, to: Nail) {
+        for (i, coverage) in self.chords.get(from, to).unwrap().iter() {
+            self.residual[i] -= self.opacity * coverage * self.darkness;
+        }
+    }
+
+    /// Darkness still missing from every pixel, row major. Negative where
+    /// lines made the art darker than the source image.
+    pub fn residual(&self) -> &Vec<f32> {
+        &self.residual
+    }
+}
+
+impl ArtAlgo for Grayscale {
+    fn initial_nails(&self) -> StrandPositions {
+        self.start
+            .map(|nail| HashMap::from([(self.color, nail)]))
+            .unwrap_or_default()
+    }
+
+    fn palette(&self) -> Vec<Rgb<u8>> {
+        vec![self.color]
+    }
+
+    fn next_nail(&mut self, nails: &StrandPositions) -> Option<(Rgb<u8>, Nail)> {
+        let current = *nails.get(&self.color)?;
+
+        let mut best_improvement = self.min_improvement;
+        let mut best_nail = None;
+
+        // in board order, so equal chords go to the lowest nail index
+        for next_nail in &self.nails {
+            // going straight back along the same chord only doubles it up
+            if Some(*next_nail) == self.last_nail {
+                continue;
+            }
+
+            let Some(improvement) = self.improvement(current, *next_nail) else {
+                continue;
+            };
+            if improvement > best_improvement {
+                best_improvement = improvement;
+                best_nail = Some(*next_nail);
+            }
+        }
+
+        let next_nail = best_nail?;
+        self.darken_path(current, next_nail);
+        self.last_nail = Some(current);
+
+        Some((self.color, next_nail))
+    }
+
+    fn line_opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::to_value(GrayscaleState {
+            residual: self.residual.clone(),
+            last_nail: self.last_nail,
+        })
+        .unwrap()
+    }
+
+    fn restore_state(
+        &mut self,
+        state: serde_json::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let state: GrayscaleState = serde_json::from_value(state)?;
+
+        if state.residual.len() != self.residual.len() {
+            return Err(format!(
+                "residual has {} pixels, the board has {}",
+                state.residual.len(),
+                self.residual.len()
+            )
+            .into());
+        }
+
+        self.residual = state.residual;
+        self.last_nail = state.last_nail;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, RgbImage};
+
+    // a white 20x20 board with a black line across the middle, right between
+    // the nails at 3 and 9 o'clock
+    fn striped_board() -> (Board, DynamicImage) {
+        let board = Board::new(20.0, 8, 1.0).unwrap();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(20, 20, |_, y| {
+            if y == 10 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+
+        (board, img)
+    }
+
+    #[test]
+    fn test_follows_the_dark_line() {
+        let (board, img) = striped_board();
+        let black = Rgb([0, 0, 0]);
+        let (right, left) = (board.nails()[0], board.nails()[4]);
+
+        let mut grayscale = Grayscale::new(&board, &img, black, 1.0, 0.0);
+
+        let start = grayscale.initial_nails()[&black];
+        assert!(start == right || start == left);
+
+        let (color, next_nail) = grayscale
+            .next_nail(&HashMap::from([(black, start)]))
+            .expect("no next nail found");
+        assert_eq!(color, black);
+        assert_eq!(next_nail, if start == right { left } else { right });
+
+        // the line is drawn, nothing else would make the art better
+        assert!((1..19).all(|x| grayscale.residual()[10 * 20 + x].abs() < 0.1));
+        assert_eq!(
+            grayscale.next_nail(&HashMap::from([(black, next_nail)])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_opacity_builds_up() {
+        let board = Board::new(20.0, 8, 1.0).unwrap();
+        let img = DynamicImage::ImageLuma8(image::GrayImage::from_pixel(20, 20, Luma([0])));
+        let black = Rgb([0, 0, 0]);
+
+        let mut grayscale = Grayscale::new(&board, &img, black, 0.25, 0.0);
+        let (right, left) = (board.nails()[0], board.nails()[4]);
+        assert_eq!(grayscale.line_opacity(), 0.25);
+
+        grayscale.darken_path(right, left);
+        grayscale.darken_path(left, right);
+        assert_eq!(grayscale.residual()[(10 * 20 + 10) as usize], 0.5);
+    }
+
+    #[test]
+    fn test_thread_luminance() {
+        let board = Board::new(20.0, 8, 1.0).unwrap();
+        let img = DynamicImage::ImageLuma8(image::GrayImage::from_pixel(20, 20, Luma([0])));
+        let (right, left) = (board.nails()[0], board.nails()[4]);
+
+        let mut gray = Grayscale::new(&board, &img, Rgb([128, 128, 128]), 1.0, 0.0);
+        gray.darken_path(right, left);
+        let residual = gray.residual()[10 * 20 + 10];
+        assert!((residual - 128.0 / 255.0).abs() < 1e-6, "{}", residual);
+
+        // a white thread on a white board never makes anything darker
+        let white = Grayscale::new(&board, &img, Rgb([255, 255, 255]), 1.0, 0.0);
+        assert!(white.initial_nails().is_empty());
+    }
+
+    #[test]
+    fn test_save_and_restore_state() {
+        let (board, img) = striped_board();
+        let black = Rgb([0, 0, 0]);
+
+        let mut grayscale = Grayscale::new(&board, &img, black, 1.0, 0.0);
+        let start = grayscale.initial_nails();
+        let (_, next_nail) = grayscale.next_nail(&start).unwrap();
+
+        let mut resumed = Grayscale::new(&board, &img, black, 1.0, 0.0);
+        resumed.restore_state(grayscale.save_state()).unwrap();
+        assert_eq!(resumed.residual(), grayscale.residual());
+        assert_eq!(
+            resumed.next_nail(&HashMap::from([(black, next_nail)])),
+            None
+        );
+
+        let small = serde_json::json!({ "residual": [0.0], "last_nail": null });
+        assert!(resumed.restore_state(small).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use image::{Primitive, Rgb};
+use palette::{ColorDifference, IntoColor, Lab, Oklab, Srgb};
+use serde::{Deserialize, Serialize};
+
+/// How far apart two colors are, for picking the closest palette color.
+///
+/// Plain RGB distance treats a step in any channel the same, while people
+/// see some steps, like adding blue to yellow, as much smaller than others.
+/// The other distances measure in spaces built so equal steps look about
+/// equally large: CIELAB with the ΔE76 or ΔE2000 formulas, or OKLab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorDistance {
+    /// Squared distance between sRGB channel values
+    #[default]
+    Rgb,
+    /// Squared ΔE76, the distance in CIELAB
+    Cie76,
+    /// Squared ΔE2000, CIELAB corrected for hue and saturation, the slowest
+    Ciede2000,
+    /// Squared distance in OKLab
+    Oklab,
+}
+
+/// Channel values [`ColorDistance`] takes, from 0 to `MAX`.
+pub trait Channel: Primitive + Into<f32> {
+    const MAX: f32;
+}
+
+impl Channel for u8 {
+    const MAX: f32 = 255.0;
+}
+
+impl Channel for f32 {
+    const MAX: f32 = 1.0;
+}
+
+impl ColorDistance {
+    /// `color` in the space the distance is measured in. RGB keeps the channel
+    /// values as they are, so it measures in the units they come in.
+    pub fn coordinates<T: Channel>(self, color: Rgb<T>) -> [f32; 3] {
+        let channels = color.0.map(Into::into);
+        let max = T::MAX;
+        let srgb = Srgb::new(channels[0] / max, channels[1] / max, channels[2] / max);
+
+        match self {
+            ColorDistance::Rgb => channels,
+            ColorDistance::Cie76 | ColorDistance::Ciede2000 => {
+                let lab: Lab = srgb.into_color();
+                [lab.l, lab.a, lab.b]
+            }
+            ColorDistance::Oklab => {
+                let oklab: Oklab = srgb.into_color();
+                [oklab.l, oklab.a, oklab.b]
+            }
+        }
+    }
+
+    /// The 8 bit color at `coordinates` given as [`ColorDistance::coordinates`],
+    /// clamped to what sRGB can show.
+    pub fn from_coordinates(self, coordinates: [f32; 3]) -> Rgb<u8> {
+        let [x, y, z] = coordinates;
+        let srgb: Srgb = match self {
+            ColorDistance::Rgb => return Rgb(coordinates.map(to_channel)),
+            ColorDistance::Cie76 | ColorDistance::Ciede2000 => Lab::new(x, y, z).into_color(),
+            ColorDistance::Oklab => Oklab::new(x, y, z).into_color(),
+        };
+
+        Rgb([srgb.red, srgb.green, srgb.blue].map(|channel| to_channel(channel * 255.0)))
+    }
+
+    /// The squared distance between two colors given as
+    /// [`ColorDistance::coordinates`]. Each kind has its own units, see
+    /// [`ColorDistance::scale`].
+    pub fn between(self, a: [f32; 3], b: [f32; 3]) -> f32 {
+        match self {
+            ColorDistance::Ciede2000 => {
+                let difference =
+                    Lab::new(a[0], a[1], a[2]).get_color_difference(&Lab::new(b[0], b[1], b[2]));
+                difference * difference
+            }
+            _ => (0..3).map(|c| (a[c] - b[c]) * (a[c] - b[c])).sum(),
+        }
+    }
+
+    pub fn distance<T: Channel>(self, a: Rgb<T>, b: Rgb<T>) -> f32 {
+        self.between(self.coordinates(a), self.coordinates(b))
+    }
+
+    /// What to multiply distances between colors with channels from 0 to 1
+    /// by, to get black and white 3 apart as in RGB. Thresholds on summed
+    /// distances then mean about the same whatever the kind.
+    pub fn scale(self) -> f32 {
+        3.0 / self.distance(Rgb([0.0f32; 3]), Rgb([1.0f32; 3]))
+    }
+
+    /// Index of the color in `palette`, given as coordinates, closest to
+    /// `color`, the first of equally close ones.
+    pub fn closest(self, color: [f32; 3], palette: &[[f32; 3]]) -> usize {
+        let mut closest = 0;
+        let mut min_distance = f32::INFINITY;
+
+        for (i, other) in palette.iter().enumerate() {
+            let distance = self.between(color, *other);
+            if distance < min_distance {
+                closest = i;
+                min_distance = distance;
+            }
+        }
+
+        closest
+    }
+}
+
+fn to_channel(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perceptual_distances() {
+        let yellow = Rgb([255u8, 255, 0]);
+        let pale_yellow = Rgb([255u8, 255, 64]);
+        let gray = Rgb([128u8, 128, 128]);
+        let pink_gray = Rgb([160u8, 128, 128]);
+
+        // blue added to yellow is a big step in RGB that is hard to see
+        let rgb = ColorDistance::Rgb;
+        assert_eq!(rgb.distance(yellow, pale_yellow), 4096.0);
+        assert_eq!(rgb.distance(gray, pink_gray), 1024.0);
+        for distance in [
+            ColorDistance::Cie76,
+            ColorDistance::Ciede2000,
+            ColorDistance::Oklab,
+        ] {
+            assert_eq!(distance.distance(gray, gray), 0.0);
+            assert!(distance.distance(yellow, pale_yellow) < distance.distance(gray, pink_gray));
+        }
+
+        for distance in [
+            ColorDistance::Rgb,
+            ColorDistance::Cie76,
+            ColorDistance::Ciede2000,
+            ColorDistance::Oklab,
+        ] {
+            let black_to_white = distance.distance(Rgb([0.0f32; 3]), Rgb([1.0f32; 3]));
+            assert!((black_to_white * distance.scale() - 3.0).abs() < 1e-4);
+        }
+        assert_eq!(ColorDistance::Rgb.scale(), 1.0);
+
+        let white = ColorDistance::Cie76.coordinates(Rgb([255u8, 255, 255]));
+        assert!((white[0] - 100.0).abs() < 0.01);
+        assert_eq!(
+            ColorDistance::Oklab.coordinates(Rgb([1.0f32, 1.0, 1.0])),
+            ColorDistance::Oklab.coordinates(Rgb([255u8, 255, 255]))
+        );
+    }
+
+    #[test]
+    fn test_from_coordinates() {
+        for distance in [
+            ColorDistance::Rgb,
+            ColorDistance::Cie76,
+            ColorDistance::Ciede2000,
+            ColorDistance::Oklab,
+        ] {
+            for color in [Rgb([0u8, 0, 0]), Rgb([255, 255, 0]), Rgb([107, 96, 122])] {
+                assert_eq!(
+                    distance.from_coordinates(distance.coordinates(color)),
+                    color
+                );
+            }
+        }
+
+        // outside sRGB
+        let lab = ColorDistance::Cie76;
+        assert_eq!(
+            lab.from_coordinates([120.0, 0.0, 0.0]),
+            Rgb([255, 255, 255])
+        );
+    }
+}
@@ -7,7 +7,19 @@ use image::Rgb;
 
 use crate::util::ColorPalette;
 
-pub(crate) fn dither_image(image: &DynamicImage, palette: ColorPalette) -> DynamicImage {
+use super::ColorDistance;
+
+/// `image` in only the colors of `palette`, with Floyd-Steinberg error
+/// diffusion. Each pixel gets the palette color closest by `distance`.
+pub fn dither_image(
+    image: &DynamicImage,
+    palette: ColorPalette,
+    distance: ColorDistance,
+) -> DynamicImage {
+    let coordinates = palette
+        .iter()
+        .map(|color| distance.coordinates(*color))
+        .collect::<Vec<_>>();
     let mut cloned_image = image.clone().to_rgb8();
     let width = cloned_image.width();
     let height = cloned_image.height();
@@ -15,7 +27,8 @@ pub(crate) fn dither_image(image: &DynamicImage, palette: ColorPalette) -> Dynam
     for y in 0..height {
         for x in 0..width {
             let pixel_color = *cloned_image.get_pixel(x, y);
-            let closest_color = find_closest_color(pixel_color, palette);
+            let closest_color =
+                palette[distance.closest(distance.coordinates(pixel_color), &coordinates)];
             let quant_error = calculate_quantization_error(pixel_color, closest_color);
 
             cloned_image.put_pixel(x, y, closest_color);
@@ -23,12 +36,10 @@ pub(crate) fn dither_image(image: &DynamicImage, palette: ColorPalette) -> Dynam
         }
     }
 
-    let dithered = DynamicImage::ImageRgb8(cloned_image);
-
-    dithered
+    DynamicImage::ImageRgb8(cloned_image)
 }
 
-pub(crate) fn get_color_masks(
+pub fn get_color_masks(
     dithered_image: &DynamicImage,
     palette: ColorPalette,
 ) -> HashMap<Rgb<u8>, Vec<Vec<bool>>> {
@@ -37,7 +48,7 @@ pub(crate) fn get_color_masks(
     let mut color_masks = HashMap::new();
     let (width, height) = dithered_image.dimensions();
 
-    palette.iter().enumerate().for_each(|(_i, color)| {
+    palette.iter().for_each(|color| {
         let mask: Vec<_> = vec![vec![false; height as usize]; width as usize];
         color_masks.insert(*color, mask);
     });
@@ -53,29 +64,6 @@ pub(crate) fn get_color_masks(
     color_masks
 }
 
-fn find_closest_color(pixel: Rgb<u8>, palette: ColorPalette) -> Rgb<u8> {
-    let mut closest_color = palette[0];
-    let mut min_distance = calculate_color_distance(pixel, closest_color);
-
-    for color in palette {
-        let distance = calculate_color_distance(pixel, *color);
-        if distance < min_distance {
-            closest_color = *color;
-            min_distance = distance;
-        }
-    }
-
-    closest_color
-}
-
-fn calculate_color_distance(color1: Rgb<u8>, color2: Rgb<u8>) -> u32 {
-    let r_diff = color1[0] as i32 - color2[0] as i32;
-    let g_diff = color1[1] as i32 - color2[1] as i32;
-    let b_diff = color1[2] as i32 - color2[2] as i32;
-
-    (r_diff * r_diff + g_diff * g_diff + b_diff * b_diff) as u32
-}
-
 fn calculate_quantization_error(pixel: Rgb<u8>, closest_color: Rgb<u8>) -> Rgb<i32> {
     let r_error = pixel[0] as i32 - closest_color[0] as i32;
     let g_error = pixel[1] as i32 - closest_color[1] as i32;
@@ -122,9 +110,9 @@ fn distribute_error_to_pixel(
     let g = pixel[1] as i32 + (quant_error[1] as f32 * factor) as i32;
     let b = pixel[2] as i32 + (quant_error[2] as f32 * factor) as i32;
 
-    let clamped_r = r.max(0).min(255) as u8;
-    let clamped_g = g.max(0).min(255) as u8;
-    let clamped_b = b.max(0).min(255) as u8;
+    let clamped_r = r.clamp(0, 255) as u8;
+    let clamped_g = g.clamp(0, 255) as u8;
+    let clamped_b = b.clamp(0, 255) as u8;
 
     image.put_pixel(x, y, Rgb([clamped_r, clamped_g, clamped_b]));
 }
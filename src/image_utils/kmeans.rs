@@ -1,12 +1,19 @@
 use image::{ImageBuffer, Rgb};
 use rand::Rng;
 
-use crate::util::ColorPalette;
+use super::ColorDistance;
 
-pub fn kmeans(k: usize, image: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<Rgb<u8>> {
+/// The `k` colors that best sum up `image`, with pixels going to the closest
+/// centroid by `distance`. Starting centroids are drawn from `rng`, so the
+/// same seed always gives the same palette.
+pub fn kmeans<R: Rng>(
+    k: usize,
+    image: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    distance: ColorDistance,
+    rng: &mut R,
+) -> Vec<Rgb<u8>> {
     // Step 1: Initialize centroids randomly
     let mut centroids: Vec<Rgb<u8>> = Vec::new();
-    let mut rng = rand::thread_rng();
     for _ in 0..k {
         let random_pixel = image.get_pixel(
             rng.gen_range(0..image.width()),
@@ -15,6 +22,12 @@ pub fn kmeans(k: usize, image: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<Rgb<u8>> {
         centroids.push(*random_pixel);
     }
 
+    // converting every pixel once, not on every iteration
+    let pixels = image
+        .pixels()
+        .map(|pixel| distance.coordinates(*pixel))
+        .collect::<Vec<_>>();
+
     // Step 4: Repeat steps 2 and 3 until convergence or maximum iterations
     let max_iterations = 100;
     let mut iteration = 0;
@@ -23,9 +36,9 @@ pub fn kmeans(k: usize, image: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<Rgb<u8>> {
     while !converged && iteration < max_iterations {
         let prev_centroids = centroids.clone();
 
-        let assignments = assign_pixels_to_centroids(&centroids, &image);
+        let assignments = assign_pixels_to_centroids(&centroids, &pixels, distance);
 
-        update_centroids(&mut centroids, &assignments, &image);
+        update_centroids(&mut centroids, &assignments, &pixels, distance);
 
         converged = centroids
             .iter()
@@ -39,56 +52,74 @@ pub fn kmeans(k: usize, image: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<Rgb<u8>> {
 }
 
 fn assign_pixels_to_centroids(
-    centroids: ColorPalette,
-    image: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    centroids: &[Rgb<u8>],
+    pixels: &[[f32; 3]],
+    distance: ColorDistance,
 ) -> Vec<usize> {
-    let mut assignments = Vec::<usize>::new();
-    for (_i, pixel) in image.pixels().enumerate() {
-        let mut min_distance = u32::MAX;
-        let mut closest_centroid = 0;
-        for (j, centroid) in centroids.iter().enumerate() {
-            let distance = calculate_distance(pixel, centroid);
-            if distance < min_distance {
-                min_distance = distance;
-                closest_centroid = j;
-            }
-        }
-        assignments.push(closest_centroid);
-    }
+    let centroids = centroids
+        .iter()
+        .map(|centroid| distance.coordinates(*centroid))
+        .collect::<Vec<_>>();
 
-    assignments
+    pixels
+        .iter()
+        .map(|pixel| distance.closest(*pixel, &centroids))
+        .collect()
 }
 
+/// Move every centroid to the mean of its pixels, taken in the space
+/// `distance` measures in so the mean is the color closest to all of them.
 fn update_centroids(
-    centroids: &mut Vec<Rgb<u8>>,
+    centroids: &mut [Rgb<u8>],
     assignments: &[usize],
-    image: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    pixels: &[[f32; 3]],
+    distance: ColorDistance,
 ) {
     for (i, centroid) in centroids.iter_mut().enumerate() {
-        let mut sum_red = 0;
-        let mut sum_green = 0;
-        let mut sum_blue = 0;
+        let mut sum = [0.0f64; 3];
         let mut count = 0;
-        for (j, assignment) in assignments.iter().enumerate() {
+        for (pixel, assignment) in pixels.iter().zip(assignments) {
             if *assignment == i {
-                let pixel = image.get_pixel(j as u32 % image.width(), j as u32 / image.width());
-                sum_red += pixel[0] as u32;
-                sum_green += pixel[1] as u32;
-                sum_blue += pixel[2] as u32;
+                for c in 0..3 {
+                    sum[c] += pixel[c] as f64;
+                }
                 count += 1;
             }
         }
         if count > 0 {
-            centroid[0] = (sum_red / count) as u8;
-            centroid[1] = (sum_green / count) as u8;
-            centroid[2] = (sum_blue / count) as u8;
+            let mean = sum.map(|total| (total / count as f64) as f32);
+            *centroid = distance.from_coordinates(mean);
         }
     }
 }
 
-fn calculate_distance(pixel1: &Rgb<u8>, pixel2: &Rgb<u8>) -> u32 {
-    let red_diff = pixel1[0] as i32 - pixel2[0] as i32;
-    let green_diff = pixel1[1] as i32 - pixel2[1] as i32;
-    let blue_diff = pixel1[2] as i32 - pixel2[2] as i32;
-    (red_diff * red_diff + green_diff * green_diff + blue_diff * blue_diff) as u32
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_same_seed_same_palette() {
+        let image = ImageBuffer::from_fn(32, 32, |x, y| {
+            Rgb([(x * 8) as u8, (y * 8) as u8, ((x + y) * 4) as u8])
+        });
+
+        let palette = kmeans(4, &image, ColorDistance::Rgb, &mut StdRng::seed_from_u64(7));
+        assert_eq!(palette.len(), 4);
+        assert_eq!(
+            palette,
+            kmeans(4, &image, ColorDistance::Rgb, &mut StdRng::seed_from_u64(7))
+        );
+    }
+
+    #[test]
+    fn test_averages_in_distance_space() {
+        // half black, half white: the mean gray is lighter in RGB than in
+        // CIELAB, where it sits halfway in lightness
+        let image = ImageBuffer::from_fn(2, 2, |x, _| Rgb([if x == 0 { 0 } else { 255 }; 3]));
+        let mean = |distance| kmeans(1, &image, distance, &mut StdRng::seed_from_u64(0))[0];
+
+        assert_eq!(mean(ColorDistance::Rgb), Rgb([128, 128, 128]));
+        assert_eq!(mean(ColorDistance::Cie76), Rgb([119, 119, 119]));
+    }
 }
@@ -1,5 +1,7 @@
+mod color_space;
 mod dither;
 mod kmeans;
 
-pub(crate) use dither::*;
-pub(crate) use kmeans::*;
+pub use color_space::*;
+pub use dither::*;
+pub use kmeans::*;
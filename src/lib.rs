@@ -0,0 +1,39 @@
+//! Turn images into string art: a thread pattern that winds colored threads
+//! from nail to nail around a board.
+//!
+//! A typical run builds a [`Board`], picks a palette (see [`image_utils::kmeans`]),
+//! hands an [`ArtAlgo`] such as [`Stringifier`] or [`Grayscale`] to an
+//! [`ArtGenerator`] and steps it until the algorithm runs out of useful chords.
+//! The finished pattern and art can then be saved with the functions in
+//! [`export`].
+
+pub mod art_algo;
+pub mod art_generator;
+pub mod beam_search;
+pub mod blender;
+pub mod board;
+pub mod checkpoint;
+pub mod chords;
+pub mod config;
+pub mod export;
+pub mod grayscale;
+pub mod image_utils;
+pub mod nail_list;
+pub mod outline;
+pub mod raster;
+pub mod refine;
+pub mod score_cache;
+pub mod stringifier;
+pub mod util;
+
+pub use art_algo::{ArtAlgo, BlendMode, StrandPositions};
+pub use art_generator::{ArtGenerator, NailPattern};
+pub use beam_search::BeamSearch;
+pub use blender::Blender;
+pub use board::{Board, Nail};
+pub use checkpoint::Checkpoint;
+pub use chords::{Chord, Chords};
+pub use config::Config;
+pub use grayscale::Grayscale;
+pub use stringifier::Stringifier;
+pub use util::{ColorPalette, Dimensions};
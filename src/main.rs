@@ -1,114 +1,393 @@
-use art_generator::ArtGenerator;
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
-use image_utils::kmeans;
+use clap::Parser;
+use cli::{Cli, Command, PaletteChoice};
+use image::{DynamicImage, Pixel, Rgb};
+use rand::{rngs::StdRng, SeedableRng};
 use std::{path::Path, rc::Rc, time::Instant};
-use stringifier::Stringifier;
-mod board;
-use board::Board;
-mod art_algo;
-mod art_generator;
-mod image_utils;
-mod stringifier;
-mod util;
+use stringify::{
+    config::{AlgorithmKind, ThreadConfig},
+    export::{
+        render_preview, save_art_svg, save_mask_images, save_template, BuildAnimation,
+        BuildInstructions, ThreadStyle,
+    },
+    image_utils::{dither_image, get_color_masks, kmeans},
+    refine::{refine, RefineOptions},
+    util::to_hex_color,
+    ArtAlgo, ArtGenerator, BeamSearch, Blender, Board, Checkpoint, Config, Grayscale, Stringifier,
+};
+mod cli;
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
 
 fn main() {
-    let nail_spacing_pixels = 3;
-    let nail_count = 200;
-
-    // load
-    let src_img = load_src_image("pikachu.jpg").expect("Failed to load image");
-
-    // board
-    let board = Rc::new(Board::new(nail_spacing_pixels, nail_count));
-
-    // scale
-    // let scaled_img = board.scale_image(&src_img, None);
-    // let scaled_img = image::open(Path::new("imgout/scaled.png")).expect("Failed to load image");
-
-    // let palette = [
-    //     Rgb([137, 111, 78]),
-    //     Rgb([131, 159, 104]),
-    //     Rgb([113, 121, 137]),
-    //     Rgb([255, 255, 255]),
-    //     Rgb([76, 82, 75]),
-    // ]
-    // .to_vec();
-
-    // let palette = kmeans(5, &src_img.to_rgb8());
-
-    // pikachu palette
-    let palette = vec![
-        Rgb([214, 186, 189]),
-        Rgb([107, 96, 122]),
-        Rgb([20, 9, 23]),
-        Rgb([183, 108, 57]),
-        Rgb([71, 45, 45]),
-        // cheeks
-        Rgb([150, 32, 18]),
-    ];
-
-    // let dithered = dither_image(&scaled_img, &palette);
-    // save_output_image(&dithered, "dithered.png");
-
-    // masks
-    // let color_masks = get_color_masks(&dithered, &palette);
-    // save_mask_images(&color_masks, dithered);
-
-    let algo = Stringifier::new(&board, &src_img, &palette);
-    let mut generator = ArtGenerator::new(Rc::clone(&board), Box::new(algo));
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Generate(args) => generate(*args, cli.seed.unwrap_or_default()),
+        Command::Run(args) => run(args, cli.seed),
+        Command::Resume(args) => resume(args),
+        Command::Palette(args) => palette(args, cli.seed.unwrap_or_default()),
+        Command::Dither(args) => dither(args, cli.seed.unwrap_or_default()),
+        Command::Preview(args) => preview(args),
+        Command::Template(args) => template(args),
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn generate(args: cli::GenerateArgs, seed: u64) -> CliResult {
+    let config = args.to_config(seed);
+    config.validate()?;
+
+    if let Some(path) = &args.save_config {
+        // paths in a config file are relative to where it is, not to where
+        // it was saved from
+        let current_dir = std::env::current_dir()?;
+        let config_dir = current_dir.join(path.parent().unwrap_or(Path::new("")));
+        let mut saved = config.clone();
+        saved.relative_paths(&current_dir, &config_dir);
+        saved.save(path)?;
+        println!("Saved config to {}", path.display());
+    }
+
+    run_job(&config)
+}
+
+fn run(args: cli::RunArgs, seed: Option<u64>) -> CliResult {
+    let mut config = Config::load(&args.config)?;
+    if let Some(seed) = seed {
+        config.seed = seed;
+    }
+    run_job(&config)
+}
+
+fn run_job(config: &Config) -> CliResult {
+    let src_img = image::open(&config.input)?;
+    let board = Rc::new(config.build_board(&src_img)?);
+    let palette = config.palette(&src_img)?;
+
+    if let Some(path) = &config.output.dithered {
+        let scaled_img = board.scale_image(&src_img, None);
+        dither_image(&scaled_img, &palette, config.threads.color_distance).save(path)?;
+    }
+
+    let algo = build_algo(config, &board, &src_img, &palette);
+    let generator = ArtGenerator::new(Rc::clone(&board), algo);
+
+    // a resumed run has to use the palette k-means came up with this time, and
+    // may not run from the same directory
+    let mut config = config.clone();
+    config.threads = ThreadConfig {
+        colors: Some(palette),
+        extract: None,
+        ..config.threads
+    };
+    config.resolve_paths(&std::env::current_dir()?);
+
+    string_art(&config, &board, generator, 0, 0)
+}
+
+fn resume(args: cli::ResumeArgs) -> CliResult {
+    let checkpoint = Checkpoint::load(&args.checkpoint)?;
+    let mut config = checkpoint.config.clone();
+    if args.max_steps.is_some() {
+        config.stop.max_steps = args.max_steps;
+    }
+
+    let src_img = image::open(&config.input)?;
+    let board = Rc::new(config.build_board(&src_img)?);
+    let palette = config.palette(&src_img)?;
+
+    let mut algo = build_algo(&config, &board, &src_img, &palette);
+    algo.restore_state(checkpoint.algo_state.clone())?;
+    let generator = ArtGenerator::resume(
+        Rc::clone(&board),
+        algo,
+        checkpoint.current_nails(),
+        checkpoint.pattern.clone(),
+    );
+    println!("Resuming after {} steps", checkpoint.step);
+
+    string_art(
+        &config,
+        &board,
+        generator,
+        checkpoint.step,
+        checkpoint.animation_frames,
+    )
+}
+
+fn build_algo(
+    config: &Config,
+    board: &Board,
+    src_img: &DynamicImage,
+    palette: &[Rgb<u8>],
+) -> Box<dyn ArtAlgo> {
+    match config.algorithm.kind {
+        AlgorithmKind::Stringifier => Box::new(Stringifier::new(
+            board,
+            src_img,
+            palette,
+            config.threads.color_distance,
+        )),
+        AlgorithmKind::Grayscale => Box::new(Grayscale::new(
+            board,
+            src_img,
+            palette[0],
+            config.algorithm.line_opacity(),
+            config.algorithm.min_improvement(),
+        )),
+        AlgorithmKind::Blend => Box::new(Blender::new(
+            board,
+            src_img,
+            palette,
+            config.algorithm.background(),
+            config.algorithm.line_opacity(),
+            config.algorithm.min_improvement(),
+            config.threads.color_distance,
+        )),
+        AlgorithmKind::BeamSearch => Box::new(BeamSearch::new(
+            Stringifier::new(board, src_img, palette, config.threads.color_distance),
+            config.algorithm.beam_width(),
+            config.algorithm.beam_depth(),
+        )),
+    }
+}
+
+/// Step `generator` from `step` on until the job is done and save everything
+/// it asks for. The animation carries on after its first `frames` frames.
+fn string_art(
+    config: &Config,
+    board: &Board,
+    mut generator: ArtGenerator,
+    mut step: usize,
+    frames: usize,
+) -> CliResult {
+    let mut animation = match &config.output.animation {
+        Some(animation) => Some((
+            BuildAnimation::resume(
+                &animation.path,
+                config.algorithm.background(),
+                animation.frame_delay_ms(),
+                frames,
+            )?,
+            animation.every,
+        )),
+        None => None,
+    };
+    // the animation's frames go to disk first, so the frame count the
+    // checkpoint records is there to resume from after a crash
+    let save_checkpoint = |path: &Path,
+                           step: usize,
+                           generator: &ArtGenerator,
+                           animation: &mut Option<(BuildAnimation, usize)>|
+     -> CliResult {
+        let frames = match animation {
+            Some((animation, _)) => {
+                animation.flush()?;
+                animation.frame_count()
+            }
+            None => 0,
+        };
+        Checkpoint::new(config, step, generator)
+            .with_animation_frames(frames)
+            .save(path)
+    };
 
     let start = Instant::now();
 
-    let mut step = 0;
-    while generator.step().is_some() {
-        if step % 100 == 0 && step != 0 {
+    while config.stop.max_steps.is_none_or(|max| step < max) && generator.step().is_some() {
+        step += 1;
+        if step.is_multiple_of(100) {
             println!("Step: {}", step);
-            // save_output_image(generator.art(), "art.png");
         }
-        step += 1;
+        if let Some(save_every) = config.output.save_every {
+            if step.is_multiple_of(save_every) {
+                generator.art().save(&config.output.art)?;
+            }
+        }
+        if let Some((animation, every)) = &mut animation {
+            if step.is_multiple_of(*every) {
+                animation.add_frame(generator.art())?;
+            }
+        }
+        if let Some(checkpoint) = &config.output.checkpoint {
+            if step.is_multiple_of(checkpoint.every) {
+                save_checkpoint(&checkpoint.path, step, &generator, &mut animation)?;
+            }
+        }
     }
     println!("Completed after {} steps", step);
+    println!("Pattern length: {} nails", generator.pattern().len());
+    for (color, length) in generator.thread_lengths_mm() {
+        println!("Thread {}: {:.2} m", to_hex_color(&color), length / 1000.0);
+    }
     println!("Elapsed time: {:?}", start.elapsed());
 
-    let pattern = generator.pattern();
-    let art = generator.art();
-    save_output_image(art, "art.png");
+    if let Some(checkpoint) = &config.output.checkpoint {
+        // lets a finished run be carried on with a higher step limit, so it
+        // is saved before refining takes the pattern away from the algorithm
+        save_checkpoint(&checkpoint.path, step, &generator, &mut animation)?;
+        println!("Saved checkpoint to {}", checkpoint.path.display());
+    }
+
+    if let Some(refine_config) = &config.refine {
+        let start = Instant::now();
+        let options = RefineOptions {
+            iterations: refine_config.iterations,
+            temperature: refine_config.temperature,
+            opacity: generator.line_opacity(),
+            blend_mode: generator.blend_mode(),
+            background: config.algorithm.background(),
+            color_distance: config.threads.color_distance,
+            seed: config.seed,
+        };
+        let target = image::open(&config.input)?;
+        let (pattern, report) = refine(board, &target, generator.pattern(), &options);
+
+        println!(
+            "Refined pattern: error {:.5} -> {:.5}, kept {} of {} changes, {} nails",
+            report.error_before,
+            report.error_after,
+            report.accepted,
+            refine_config.iterations,
+            pattern.len()
+        );
+        println!("Elapsed time: {:?}", start.elapsed());
+        generator.replace_pattern(pattern);
+    }
+
+    generator.art().save(&config.output.art)?;
+    println!("Saved art to {}", config.output.art.display());
 
-    // println!("Pattern: {:?}", pattern);
+    if let Some((mut animation, every)) = animation {
+        // always end on the finished art, which refining may have changed
+        // since the last frame
+        if !step.is_multiple_of(every) || config.refine.is_some() {
+            animation.add_frame(generator.art())?;
+        }
+        println!("Saved {} animation frames", animation.frame_count());
+    }
+
+    if let Some(path) = &config.output.pattern {
+        BuildInstructions::new(board, generator.pattern()).save(path)?;
+        println!("Saved build instructions to {}", path.display());
+    }
+
+    if let Some(path) = &config.output.svg {
+        // grayscale and blend art is modelled on a board of a given color
+        let background = match config.algorithm.kind {
+            AlgorithmKind::Stringifier | AlgorithmKind::BeamSearch => None,
+            AlgorithmKind::Grayscale => Some(Rgb([255, 255, 255])),
+            AlgorithmKind::Blend => Some(config.algorithm.background()),
+        };
+        let style = ThreadStyle {
+            opacity: generator.line_opacity(),
+            background,
+            ..ThreadStyle::for_board(board)
+        };
+        save_art_svg(board, generator.pattern(), &style, path)?;
+        println!("Saved vector art to {}", path.display());
+    }
+
+    if let Some(render) = &config.output.render {
+        let texture = render.texture.as_ref().map(image::open).transpose()?;
+        let style = ThreadStyle {
+            opacity: render.opacity.unwrap_or(generator.line_opacity()),
+            background: Some(config.algorithm.background()),
+            ..ThreadStyle::for_board(board)
+        };
+        render_preview(
+            board,
+            generator.pattern(),
+            &style,
+            render.pixels_per_mm(),
+            texture.as_ref(),
+        )
+        .save(&render.path)?;
+        println!("Saved preview to {}", render.path.display());
+    }
+
+    Ok(())
 }
 
-fn save_mask_images(
-    color_masks: &std::collections::HashMap<Rgb<u8>, Vec<Vec<bool>>>,
-    dithered: DynamicImage,
-) {
-    color_masks.iter().for_each(|(color, mask)| {
-        let mut img = ImageBuffer::new(dithered.width(), dithered.height());
-        for y in 0..dithered.height() {
-            for x in 0..dithered.width() {
-                let pixel_color = if mask[x as usize][y as usize] {
-                    *color
-                } else if *color == Rgb([255, 255, 255]) {
-                    Rgb([0, 0, 0])
-                } else {
-                    Rgb([255, 255, 255])
-                };
-                img.put_pixel(x, y, pixel_color);
-            }
+fn palette(args: cli::PaletteArgs, seed: u64) -> CliResult {
+    let src_img = image::open(&args.input)?;
+    let palette = kmeans(
+        args.colors,
+        &src_img.to_rgb8(),
+        args.color_distance.to_config(),
+        &mut StdRng::seed_from_u64(seed),
+    );
+
+    let hex = palette.iter().map(to_hex_color).collect::<Vec<_>>();
+    println!("{}", hex.join(","));
+
+    Ok(())
+}
+
+fn dither(args: cli::DitherArgs, seed: u64) -> CliResult {
+    let src_img = image::open(&args.input)?;
+    let board = args.board.to_config().build(&src_img)?;
+    let palette = choose_palette(&args.palette, &src_img, seed);
+
+    let scaled_img = board.scale_image(&src_img, None);
+    let dithered = dither_image(
+        &scaled_img,
+        &palette,
+        args.palette.color_distance.to_config(),
+    );
+    dithered.save(&args.output)?;
+
+    if let Some(dir) = &args.masks {
+        std::fs::create_dir_all(dir)?;
+        let color_masks = get_color_masks(&dithered, &palette);
+        save_mask_images(&color_masks, &dithered, dir)?;
+    }
+
+    Ok(())
+}
+
+fn preview(args: cli::PreviewArgs) -> CliResult {
+    let src_img = image::open(&args.input)?;
+    let board = args.board.to_config().build(&src_img)?;
+
+    let mut preview = board.scale_image(&src_img, None).to_rgb8();
+    for (x, y, pixel) in preview.enumerate_pixels_mut() {
+        if !board.contains_pixel(x, y) {
+            pixel.apply(|channel| channel / 4);
         }
-        save_output_image(
-            &DynamicImage::ImageRgb8(img),
-            &format!("mask_{}{}{}.png", color.0[0], color.0[1], color.0[2]),
-        );
-    });
+    }
+    for nail in board.nails() {
+        preview.put_pixel(nail.0, nail.1, Rgb([255, 0, 0]));
+    }
+    preview.save(&args.output)?;
+
+    Ok(())
 }
 
-fn save_output_image(image: &DynamicImage, name: &str) {
-    let path = format!("imgout/{}", name);
-    image.save(path).expect("Failed to save image");
+fn template(args: cli::TemplateArgs) -> CliResult {
+    let src_img = image::open(&args.input)?;
+    let board = args.board.to_config().build(&src_img)?;
+
+    let paths = save_template(&board, args.paper.to_paper(), &args.output)?;
+    for path in paths {
+        println!("Saved {}", path.display());
+    }
+
+    Ok(())
 }
 
-fn load_src_image(filename: &str) -> Result<DynamicImage, Box<dyn std::error::Error>> {
-    let file_path = Path::new("imgsrc").join(filename);
-    let image = image::open(file_path)?;
-    Ok(image)
+fn choose_palette(choice: &PaletteChoice, src_img: &DynamicImage, seed: u64) -> Vec<Rgb<u8>> {
+    match &choice.palette {
+        Some(palette) => palette.clone(),
+        None => kmeans(
+            choice.colors,
+            &src_img.to_rgb8(),
+            choice.color_distance.to_config(),
+            &mut StdRng::seed_from_u64(seed),
+        ),
+    }
 }
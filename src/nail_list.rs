@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::board::NailPosition;
+
+/// Load nail positions in millimetres from a `.csv` or `.json` file.
+///
+/// CSV files hold one `x,y` pair per line. Empty lines, lines starting with
+/// `#` and a header line above the first pair are ignored. JSON files hold an
+/// array of either `[x, y]` pairs or `{"x": .., "y": ..}` objects.
+pub fn load(path: &Path) -> Result<Vec<NailPosition>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)?;
+
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    let positions = match extension.as_deref() {
+        Some("csv") => parse_csv(&text)?,
+        Some("json") => parse_json(&text)?,
+        _ => return Err(format!("{} is neither .csv nor .json", path.display()).into()),
+    };
+
+    Ok(positions)
+}
+
+pub fn parse_csv(text: &str) -> Result<Vec<NailPosition>, String> {
+    let mut positions = Vec::new();
+    let mut first_line = true;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let header_allowed = std::mem::take(&mut first_line);
+
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        let coordinates = match fields.as_slice() {
+            [x, y, ..] => x.parse::<f64>().and_then(|x| Ok((x, y.parse::<f64>()?))),
+            _ => return Err(format!("line {}: expected x,y", i + 1)),
+        };
+
+        match coordinates {
+            Ok((x, y)) => positions.push(NailPosition(x, y)),
+            // a header line
+            Err(_) if header_allowed => continue,
+            Err(_) => {
+                return Err(format!(
+                    "line {}: '{}' is not a pair of numbers",
+                    i + 1,
+                    line
+                ))
+            }
+        }
+    }
+
+    Ok(positions)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonNail {
+    Pair([f64; 2]),
+    Point { x: f64, y: f64 },
+}
+
+pub fn parse_json(text: &str) -> Result<Vec<NailPosition>, String> {
+    let nails: Vec<JsonNail> = serde_json::from_str(text).map_err(|err| err.to_string())?;
+
+    Ok(nails
+        .into_iter()
+        .map(|nail| match nail {
+            JsonNail::Pair([x, y]) => NailPosition(x, y),
+            JsonNail::Point { x, y } => NailPosition(x, y),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "x_mm,y_mm\n0,0\n# centre nail\n 150.5 , 150\n\n300,0\n";
+
+        assert_eq!(
+            parse_csv(csv).unwrap(),
+            vec![
+                NailPosition(0.0, 0.0),
+                NailPosition(150.5, 150.0),
+                NailPosition(300.0, 0.0)
+            ]
+        );
+        assert!(parse_csv("0,0\nten,0\n").is_err());
+
+        // a header below a comment
+        let csv = "# nails of the big board\n\nx,y\n1,2\n";
+        assert_eq!(parse_csv(csv).unwrap(), vec![NailPosition(1.0, 2.0)]);
+        assert!(parse_csv("x,y\nx,y\n1,2\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let json = r#"[[0, 0], {"x": 150.5, "y": 150}]"#;
+
+        assert_eq!(
+            parse_json(json).unwrap(),
+            vec![NailPosition(0.0, 0.0), NailPosition(150.5, 150.0)]
+        );
+    }
+}
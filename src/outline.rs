@@ -0,0 +1,422 @@
+use std::path::Path;
+
+use crate::util::Dimensions;
+
+/// A closed board outline in millimetres. The last point connects back to the first.
+pub type Outline = Vec<(f64, f64)>;
+
+const CURVE_SEGMENTS: usize = 16;
+
+/// Load the outline of a board from a simple SVG file. See [`parse_svg`].
+pub fn load_svg(path: &Path) -> Result<Outline, Box<dyn std::error::Error>> {
+    let svg = std::fs::read_to_string(path)?;
+    Ok(parse_svg(&svg)?)
+}
+
+/// Read the first `<polygon>`, `<polyline>` or `<path>` from an SVG document.
+/// SVG user units are taken to be millimetres. Paths may use move, line and
+/// Bézier commands; curves are flattened into short line segments. Only the
+/// first sub-path is used.
+pub fn parse_svg(svg: &str) -> Result<Outline, String> {
+    let element = ["<polygon", "<polyline", "<path"]
+        .iter()
+        .filter_map(|tag| svg.find(tag).map(|start| (start, *tag)))
+        .min_by_key(|(start, _)| *start);
+
+    let outline = match element {
+        Some((start, "<path")) => {
+            let d = attribute(&svg[start..], "d").ok_or("<path> has no d attribute")?;
+            parse_path(d)?
+        }
+        Some((start, _)) => {
+            let points = attribute(&svg[start..], "points").ok_or("polygon has no points")?;
+            parse_points(points)?
+        }
+        None => return Err("no <polygon>, <polyline> or <path> found".to_string()),
+    };
+
+    if outline.len() < 3 {
+        return Err("outline needs at least 3 points".to_string());
+    }
+
+    Ok(outline)
+}
+
+/// Move the outline so its bounding box starts at the origin and scale it to
+/// be `width_mm` wide, keeping its aspect ratio. Fails for an outline with no
+/// width to scale.
+pub fn fit_to_width(outline: &[(f64, f64)], width_mm: f64) -> Result<Outline, String> {
+    let (min_x, min_y, max_x, _) = bounds(outline);
+    if max_x - min_x <= 0.0 {
+        return Err("outline has no width".to_string());
+    }
+    let scale = width_mm / (max_x - min_x);
+
+    Ok(outline
+        .iter()
+        .map(|(x, y)| ((x - min_x) * scale, (y - min_y) * scale))
+        .collect())
+}
+
+/// `(min_x, min_y, max_x, max_y)` of the outline.
+pub fn bounds(outline: &[(f64, f64)]) -> (f64, f64, f64, f64) {
+    outline.iter().fold(
+        (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+        |(min_x, min_y, max_x, max_y), (x, y)| {
+            (min_x.min(*x), min_y.min(*y), max_x.max(*x), max_y.max(*y))
+        },
+    )
+}
+
+/// Whether `point` is inside the outline, using the even-odd rule.
+pub fn contains(outline: &[(f64, f64)], point: (f64, f64)) -> bool {
+    let (px, py) = point;
+    let mut inside = false;
+
+    for i in 0..outline.len() {
+        let (x1, y1) = outline[i];
+        let (x2, y2) = outline[(i + 1) % outline.len()];
+
+        if (y1 > py) != (y2 > py) && px < x1 + (py - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+/// Row major mask of the pixels of an image with `pixels_per_mm` pixels per
+/// millimetre whose centres are inside the outline, see [`contains`]. Works
+/// row by row, so it stays fast at high resolutions.
+pub fn mask(outline: &[(f64, f64)], pixels_per_mm: f64, dimensions: &Dimensions) -> Vec<bool> {
+    let mut mask = Vec::with_capacity((dimensions.width() * dimensions.height()) as usize);
+    let mut crossings = Vec::new();
+
+    for y in 0..dimensions.height() {
+        let py = (y as f64 + 0.5) / pixels_per_mm;
+
+        // where the edges cross the row, the same crossings contains counts
+        crossings.clear();
+        for i in 0..outline.len() {
+            let (x1, y1) = outline[i];
+            let (x2, y2) = outline[(i + 1) % outline.len()];
+            if (y1 > py) != (y2 > py) {
+                crossings.push(x1 + (py - y1) * (x2 - x1) / (y2 - y1));
+            }
+        }
+        crossings.sort_by(f64::total_cmp);
+
+        let mut passed = 0;
+        for x in 0..dimensions.width() {
+            let px = (x as f64 + 0.5) / pixels_per_mm;
+            while passed < crossings.len() && crossings[passed] <= px {
+                passed += 1;
+            }
+            mask.push((crossings.len() - passed) % 2 == 1);
+        }
+    }
+
+    mask
+}
+
+/// Value of the attribute called exactly `name` on the element `element`
+/// starts with, however the attributes are spaced out.
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let is_name_end = |c: char| c == '=' || c == '>' || c == '/' || c.is_whitespace();
+
+    // skip the tag name
+    let mut rest = element
+        .trim_start_matches('<')
+        .trim_start_matches(|c| !is_name_end(c));
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == '/' || c.is_whitespace());
+        if rest.is_empty() || rest.starts_with('>') {
+            return None;
+        }
+
+        let (attribute, after) = rest.split_at(rest.find(is_name_end).unwrap_or(rest.len()));
+        let Some(after) = after.trim_start().strip_prefix('=') else {
+            // an attribute without a value
+            rest = after;
+            continue;
+        };
+
+        let after = after.trim_start();
+        let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &after[1..];
+        let end = value.find(quote)?;
+
+        if attribute == name {
+            return Some(&value[..end]);
+        }
+        rest = &value[end + 1..];
+    }
+}
+
+fn parse_points(points: &str) -> Result<Outline, String> {
+    let numbers = tokenize(points)?
+        .into_iter()
+        .map(|token| match token {
+            Token::Number(n) => Ok(n),
+            Token::Command(c) => Err(format!("unexpected '{}' in points", c)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if numbers.len() % 2 != 0 {
+        return Err("points has an odd number of coordinates".to_string());
+    }
+
+    Ok(numbers.chunks(2).map(|xy| (xy[0], xy[1])).collect())
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Command(char),
+    Number(f64),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+            tokens.push(Token::Command(c));
+            i += 1;
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+            let start = i;
+            let mut seen_dot = c == '.';
+            i += 1;
+            while i < chars.len() {
+                let c = chars[i];
+                let exponent_sign = (c == '-' || c == '+') && matches!(chars[i - 1], 'e' | 'E');
+                if c == '.' && !seen_dot {
+                    seen_dot = true;
+                } else if !(c.is_ascii_digit() || c == 'e' || c == 'E' || exponent_sign) {
+                    break;
+                }
+                i += 1;
+            }
+            let number = chars[start..i].iter().collect::<String>();
+            let number = number
+                .parse::<f64>()
+                .map_err(|_| format!("'{}' is not a number", number))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_whitespace() || c == ',' {
+            i += 1;
+        } else {
+            return Err(format!("unexpected '{}' in path", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_path(d: &str) -> Result<Outline, String> {
+    let tokens = tokenize(d)?;
+    let mut outline: Outline = Vec::new();
+    let mut current = (0.0, 0.0);
+    let mut command = None;
+    let mut i = 0;
+
+    let numbers = |i: &mut usize, count: usize| -> Result<Vec<f64>, String> {
+        let values = tokens[*i..]
+            .iter()
+            .take(count)
+            .map(|token| match token {
+                Token::Number(n) => Some(*n),
+                Token::Command(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|values| values.len() == count)
+            .ok_or("path command is missing coordinates")?;
+        *i += count;
+        Ok(values)
+    };
+
+    while i < tokens.len() {
+        if let Token::Command(c) = tokens[i] {
+            command = Some(c);
+            i += 1;
+        }
+
+        let c = command.ok_or("path must start with a command")?;
+        let relative = c.is_ascii_lowercase();
+        let offset = move |(x, y): (f64, f64)| {
+            if relative {
+                (current.0 + x, current.1 + y)
+            } else {
+                (x, y)
+            }
+        };
+
+        match c.to_ascii_uppercase() {
+            'M' => {
+                if !outline.is_empty() {
+                    // only the first sub-path describes the outline
+                    break;
+                }
+                let xy = numbers(&mut i, 2)?;
+                current = offset((xy[0], xy[1]));
+                outline.push(current);
+                // further coordinate pairs are implicit line-tos
+                command = Some(if relative { 'l' } else { 'L' });
+            }
+            'L' => {
+                let xy = numbers(&mut i, 2)?;
+                current = offset((xy[0], xy[1]));
+                outline.push(current);
+            }
+            'H' => {
+                let x = numbers(&mut i, 1)?[0];
+                current = (if relative { current.0 + x } else { x }, current.1);
+                outline.push(current);
+            }
+            'V' => {
+                let y = numbers(&mut i, 1)?[0];
+                current = (current.0, if relative { current.1 + y } else { y });
+                outline.push(current);
+            }
+            'C' => {
+                let v = numbers(&mut i, 6)?;
+                let (c1, c2, end) = (
+                    offset((v[0], v[1])),
+                    offset((v[2], v[3])),
+                    offset((v[4], v[5])),
+                );
+                outline.extend(flatten_cubic(current, c1, c2, end));
+                current = end;
+            }
+            'Q' => {
+                let v = numbers(&mut i, 4)?;
+                let (control, end) = (offset((v[0], v[1])), offset((v[2], v[3])));
+                // a quadratic is a cubic with both control points on the same spot
+                let c1 = lerp(current, control, 2.0 / 3.0);
+                let c2 = lerp(end, control, 2.0 / 3.0);
+                outline.extend(flatten_cubic(current, c1, c2, end));
+                current = end;
+            }
+            'Z' => break,
+            other => return Err(format!("unsupported path command '{}'", other)),
+        }
+    }
+
+    // drop the closing point if the path returns to its start explicitly
+    if outline.len() > 1 && outline.first() == outline.last() {
+        outline.pop();
+    }
+
+    Ok(outline)
+}
+
+fn flatten_cubic(
+    start: (f64, f64),
+    c1: (f64, f64),
+    c2: (f64, f64),
+    end: (f64, f64),
+) -> Vec<(f64, f64)> {
+    (1..=CURVE_SEGMENTS)
+        .map(|step| {
+            let t = step as f64 / CURVE_SEGMENTS as f64;
+            let a = lerp(lerp(start, c1, t), lerp(c1, c2, t), t);
+            let b = lerp(lerp(c1, c2, t), lerp(c2, end, t), t);
+            lerp(a, b, t)
+        })
+        .collect()
+}
+
+fn lerp(a: (f64, f64), b: (f64, f64), t: f64) -> (f64, f64) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_polygon() {
+        let svg = r#"<svg><polygon fill="red" points="0,0 10,0 10,5 0,5"/></svg>"#;
+
+        assert_eq!(
+            parse_svg(svg).unwrap(),
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 5.0), (0.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn test_parse_relative_path() {
+        let svg = r#"<svg><path d="M10 10 h20 v-10 l-20,0z" /></svg>"#;
+
+        assert_eq!(
+            parse_svg(svg).unwrap(),
+            vec![(10.0, 10.0), (30.0, 10.0), (30.0, 0.0), (10.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn test_attribute_names_match_exactly() {
+        let svg = "<svg><path data-d=\"M0 0 h1\"\nd='M0 0 h10 v10 h-10 z'/></svg>";
+
+        assert_eq!(parse_svg(svg).unwrap().len(), 4);
+        assert_eq!(attribute("<path data-d=\"M0 0\">", "d"), None);
+        assert_eq!(attribute("<path hidden d = \"M1 1\">", "d"), Some("M1 1"));
+    }
+
+    #[test]
+    fn test_fit_to_width() {
+        let outline = vec![(10.0, 10.0), (20.0, 10.0), (10.0, 15.0)];
+
+        assert_eq!(
+            fit_to_width(&outline, 100.0).unwrap(),
+            vec![(0.0, 0.0), (100.0, 0.0), (0.0, 50.0)]
+        );
+        assert!(fit_to_width(&[(5.0, 0.0), (5.0, 10.0), (5.0, 20.0)], 100.0).is_err());
+    }
+
+    #[test]
+    fn test_curves_are_flattened() {
+        let outline = parse_path("M0 0 C0 10 10 10 10 0 L5 -5 Z").unwrap();
+
+        assert_eq!(outline.len(), 1 + CURVE_SEGMENTS + 1);
+        assert_eq!(outline[CURVE_SEGMENTS], (10.0, 0.0));
+        assert!(outline[CURVE_SEGMENTS / 2].1 > 7.0);
+    }
+
+    #[test]
+    fn test_contains() {
+        let triangle = vec![(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)];
+
+        assert!(contains(&triangle, (2.0, 2.0)));
+        assert!(!contains(&triangle, (8.0, 8.0)));
+        assert!(!contains(&triangle, (-1.0, 2.0)));
+    }
+
+    #[test]
+    fn test_mask_matches_contains() {
+        let star = vec![
+            (10.0, 0.0),
+            (13.0, 7.0),
+            (20.0, 7.5),
+            (14.5, 12.0),
+            (16.0, 20.0),
+            (10.0, 15.5),
+            (4.0, 20.0),
+            (5.5, 12.0),
+            (0.0, 7.5),
+            (7.0, 7.0),
+        ];
+        let dimensions = Dimensions::new(60, 60);
+
+        let mask = mask(&star, 3.0, &dimensions);
+        for y in 0..60 {
+            for x in 0..60 {
+                let centre = ((x as f64 + 0.5) / 3.0, (y as f64 + 0.5) / 3.0);
+                assert_eq!(mask[y * 60 + x], contains(&star, centre), "{}, {}", x, y);
+            }
+        }
+    }
+}
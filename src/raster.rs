@@ -0,0 +1,128 @@
+use crate::util::Dimensions;
+
+/// A pixel a thread runs over and how much of that pixel the thread covers,
+/// from 0 to 1.
+pub type Coverage = (u32, u32, f32);
+
+/// Pixels covered by a thread `width` pixels wide running from `start` to
+/// `end`, with coordinates in pixels where pixel `(x, y)` spans `x..x + 1`.
+///
+/// Coverage is the overlap of the thread with each pixel measured across the
+/// thread, so a thread half a pixel wide running through the middle of a
+/// pixel covers half of it. Summed up, coverage equals the area of the thread
+/// whatever its angle. Pixels off the image are dropped.
+pub fn thread_coverage(
+    start: (f64, f64),
+    end: (f64, f64),
+    width: f64,
+    dimensions: &Dimensions,
+) -> Vec<Coverage> {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = dx.hypot(dy);
+    if length < f64::EPSILON || width <= 0.0 {
+        return Vec::new();
+    }
+
+    let (ux, uy) = (dx / length, dy / length);
+    let half_width = width / 2.0;
+    let steep = dy.abs() > dx.abs();
+
+    // walk along the major axis and look at the pixels the band can reach on
+    // the minor axis
+    let (major_start, major_end) = if steep {
+        (start.1.min(end.1), start.1.max(end.1))
+    } else {
+        (start.0.min(end.0), start.0.max(end.0))
+    };
+    let slope = if steep { dx / dy } else { dy / dx };
+    let reach = (half_width + 0.5) * (1.0 + slope * slope).sqrt() + 1.0;
+
+    let (max_major, max_minor) = if steep {
+        (dimensions.height(), dimensions.width())
+    } else {
+        (dimensions.width(), dimensions.height())
+    };
+
+    let first = major_start.floor().max(0.0) as u32;
+    let last = (major_end.ceil() as u32).min(max_major);
+
+    let mut coverage = Vec::new();
+
+    for major in first..last {
+        let centre = major as f64 + 0.5;
+        let minor_at = if steep {
+            start.0 + (centre - start.1) * slope
+        } else {
+            start.1 + (centre - start.0) * slope
+        };
+
+        let low = (minor_at - reach).floor().max(0.0) as u32;
+        let high = ((minor_at + reach).ceil().max(0.0) as u32).min(max_minor);
+
+        for minor in low..high {
+            let (x, y) = if steep {
+                (minor, major)
+            } else {
+                (major, minor)
+            };
+            let (px, py) = (x as f64 + 0.5 - start.0, y as f64 + 0.5 - start.1);
+
+            let along = px * ux + py * uy;
+            if along < 0.0 || along > length {
+                continue;
+            }
+
+            let across = px * uy - py * ux;
+            let overlap = (across + 0.5).min(half_width) - (across - 0.5).max(-half_width);
+            if overlap > 0.0 {
+                coverage.push((x, y, overlap.min(1.0) as f32));
+            }
+        }
+    }
+
+    coverage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_horizontal_thread() {
+        let dimensions = Dimensions::new(10, 10);
+        let coverage = thread_coverage((0.0, 5.0), (10.0, 5.0), 0.5, &dimensions);
+
+        // the thread runs along the border between rows 4 and 5
+        assert_eq!(coverage.len(), 20);
+        assert!(coverage
+            .iter()
+            .all(|(_, y, w)| (*y == 4 || *y == 5) && *w == 0.25));
+    }
+
+    #[test]
+    fn test_area_is_preserved() {
+        let dimensions = Dimensions::new(100, 100);
+
+        for (end, width) in [
+            ((90.0, 10.0), 0.3),
+            ((60.0, 80.0), 0.7),
+            ((50.5, 95.0), 2.0),
+        ] {
+            let start = (10.0, 10.0);
+            let coverage = thread_coverage(start, end, width, &dimensions);
+
+            let total: f64 = coverage.iter().map(|(_, _, w)| *w as f64).sum();
+            let area = (end.0 - start.0).hypot(end.1 - start.1) * width;
+            assert!((total - area).abs() / area < 0.03, "{} vs {}", total, area);
+        }
+    }
+
+    #[test]
+    fn test_thick_thread_saturates() {
+        let dimensions = Dimensions::new(10, 10);
+        let coverage = thread_coverage((0.0, 5.5), (10.0, 5.5), 3.0, &dimensions);
+
+        assert!(coverage.iter().any(|(_, y, w)| *y == 5 && *w == 1.0));
+        assert!(coverage.iter().all(|(_, _, w)| *w <= 1.0));
+    }
+}
@@ -0,0 +1,506 @@
+use std::sync::Arc;
+
+use image::{DynamicImage, Rgb};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    art_algo::BlendMode,
+    art_generator::NailPattern,
+    board::{Board, Nail},
+    chords::Chords,
+    image_utils::ColorDistance,
+    util::{to_color, Color},
+};
+
+/// How hard [`refine`] tries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefineOptions {
+    /// Changes to try
+    pub iterations: usize,
+    /// Starting temperature of simulated annealing, cooling down to 0 by the
+    /// last iteration. At 0 only changes that lower the error are kept.
+    pub temperature: f64,
+    /// How much of a pixel a thread fully running over it hides
+    pub opacity: f32,
+    /// How threads over the same pixel mix, as the algorithm draws them
+    pub blend_mode: BlendMode,
+    /// Color of the board where no thread runs
+    pub background: Rgb<u8>,
+    pub color_distance: ColorDistance,
+    pub seed: u64,
+}
+
+/// Mean distance per pixel between the modelled board and the target, by
+/// the configured [`ColorDistance`] scaled to RGB, before and after refining.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RefineReport {
+    pub error_before: f64,
+    pub error_after: f64,
+    /// Changes that were kept
+    pub accepted: usize,
+}
+
+/// Improve a finished `pattern` by removing, rerouting and swapping nails,
+/// keeping every color a single thread going from nail to nail.
+///
+/// The board is modelled the way the art is drawn, with the threads over
+/// every pixel mixed in the order they were laid by the algorithm's
+/// [`BlendMode`]. Each change only rescores the pixels of the chords it
+/// touches. Threads keep their place in the pattern, removed nails drop out.
+pub fn refine(
+    board: &Board,
+    target: &DynamicImage,
+    pattern: &NailPattern,
+    options: &RefineOptions,
+) -> (NailPattern, RefineReport) {
+    let mut model = Model::new(board, target, pattern, options);
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let error_before = model.error;
+    let mut accepted = 0;
+
+    for iteration in 0..options.iterations {
+        let cooling = 1.0 - iteration as f64 / options.iterations as f64;
+        let temperature = options.temperature * cooling;
+
+        let Some(change) = model.propose(&mut rng) else {
+            continue;
+        };
+        let delta = model.apply(&change);
+
+        let keep =
+            delta < 0.0 || (temperature > 0.0 && rng.gen::<f64>() < (-delta / temperature).exp());
+        if keep {
+            model.commit(change);
+            accepted += 1;
+        } else {
+            model.revert(&change);
+        }
+    }
+
+    let report = RefineReport {
+        error_before: error_before / model.pixel_count as f64,
+        error_after: model.error / model.pixel_count as f64,
+        accepted,
+    };
+
+    (model.pattern(), report)
+}
+
+/// Replace the nails at `start..end` of color `color`'s path with `nails`.
+/// Chords come with the entry of the nail they end at, which sets their
+/// place in the pattern.
+#[derive(Debug)]
+struct Change {
+    color: usize,
+    start: usize,
+    end: usize,
+    nails: Vec<Nail>,
+    removed: Vec<(usize, Nail, Nail)>,
+    added: Vec<(usize, Nail, Nail)>,
+}
+
+/// A thread over a pixel.
+#[derive(Debug, Clone, Copy)]
+struct Layer {
+    entry: usize,
+    color: usize,
+    alpha: f32,
+}
+
+struct Model {
+    chords: Arc<Chords>,
+    palette: Vec<Color>,
+    background: Color,
+    opacity: f32,
+    blend_mode: BlendMode,
+    color_distance: ColorDistance,
+    distance_scale: f32,
+    /// Target pixels as [`ColorDistance::coordinates`]
+    target: Vec<Color>,
+    on_board: Vec<bool>,
+    pixel_count: usize,
+    /// Threads over every pixel, in the order they were laid
+    layers: Vec<Vec<Layer>>,
+    error: f64,
+    entries: Vec<Option<(Rgb<u8>, Nail)>>,
+    /// Indices into `entries` of the nails of every color, in order
+    paths: Vec<Vec<usize>>,
+    /// Last time a pixel was counted, so pixels in several chords count once
+    marks: Vec<u32>,
+    mark: u32,
+}
+
+impl Model {
+    fn new(
+        board: &Board,
+        target: &DynamicImage,
+        pattern: &NailPattern,
+        options: &RefineOptions,
+    ) -> Self {
+        let target_img = board.scale_image(target, None).to_rgb8();
+        let width = target_img.width();
+        let target = target_img
+            .pixels()
+            .map(|pixel| options.color_distance.coordinates(*pixel))
+            .collect::<Vec<_>>();
+        let on_board = (0..target.len() as u32)
+            .map(|i| board.contains_pixel(i % width, i / width))
+            .collect::<Vec<_>>();
+
+        let mut colors: Vec<Rgb<u8>> = Vec::new();
+        let mut paths: Vec<Vec<usize>> = Vec::new();
+        for (i, (color, _)) in pattern.iter().enumerate() {
+            match colors.iter().position(|c| c == color) {
+                Some(c) => paths[c].push(i),
+                None => {
+                    colors.push(*color);
+                    paths.push(vec![i]);
+                }
+            }
+        }
+
+        let mut model = Self {
+            chords: board.chords_on_board(),
+            palette: colors.iter().map(to_color).collect(),
+            background: to_color(&options.background),
+            opacity: options.opacity,
+            blend_mode: options.blend_mode,
+            color_distance: options.color_distance,
+            distance_scale: options.color_distance.scale(),
+            pixel_count: on_board.iter().filter(|on| **on).count().max(1),
+            layers: vec![Vec::new(); target.len()],
+            error: 0.0,
+            marks: vec![0; target.len()],
+            mark: 0,
+            target,
+            on_board,
+            entries: pattern.iter().copied().map(Some).collect(),
+            paths,
+        };
+
+        for color in 0..model.paths.len() {
+            let path = model.paths[color].clone();
+            for pair in path.windows(2) {
+                let (a, b) = (model.nail(pair[0]), model.nail(pair[1]));
+                model.lay(pair[1], color, a, b);
+            }
+        }
+        model.error = (0..model.target.len())
+            .filter(|i| model.on_board[*i])
+            .map(|i| model.pixel_error(i))
+            .sum();
+
+        model
+    }
+
+    fn nail(&self, entry: usize) -> Nail {
+        self.entries[entry].unwrap().1
+    }
+
+    /// A random change to one color's path, `None` if it would need a chord
+    /// that isn't there.
+    fn propose(&self, rng: &mut StdRng) -> Option<Change> {
+        if self.paths.is_empty() {
+            return None;
+        }
+
+        let color = rng.gen_range(0..self.paths.len());
+        let entries = &self.paths[color];
+        let path = entries
+            .iter()
+            .map(|entry| self.nail(*entry))
+            .collect::<Vec<_>>();
+        if path.len() < 2 {
+            return None;
+        }
+
+        let i = rng.gen_range(0..path.len());
+        let (start, end, nails) = match rng.gen_range(0..3) {
+            // remove
+            0 => (i, i + 1, vec![]),
+            // reroute
+            1 => {
+                let nails = self.chords.nails();
+                (i, i + 1, vec![nails[rng.gen_range(0..nails.len())]])
+            }
+            // swap with the next nail
+            _ => {
+                if i + 1 >= path.len() {
+                    return None;
+                }
+                (i, i + 2, vec![path[i + 1], path[i]])
+            }
+        };
+
+        // the chords on either side of the replaced nails. New nails take
+        // over the entries of the ones they replace, in order
+        let from = start.saturating_sub(1);
+        let to = (end + 1).min(path.len());
+        let old = &path[from..to];
+        let new = [&path[from..start], &nails, &path[end..to]].concat();
+        let old_entries = &entries[from..to];
+        let new_entries = [
+            &entries[from..start],
+            &entries[start..start + nails.len()],
+            &entries[end..to],
+        ]
+        .concat();
+
+        let chords = |nails: &[Nail], entries: &[usize]| {
+            (1..nails.len())
+                .map(|k| (entries[k], nails[k - 1], nails[k]))
+                .collect::<Vec<_>>()
+        };
+        let removed = chords(old, old_entries);
+        let added = chords(&new, &new_entries);
+        if added
+            .iter()
+            .any(|(_, a, b)| self.chords.get(*a, *b).is_none())
+        {
+            return None;
+        }
+
+        Some(Change {
+            color,
+            start,
+            end,
+            nails,
+            removed,
+            added,
+        })
+    }
+
+    /// Lay the change's threads, returning how much the error changes.
+    fn apply(&mut self, change: &Change) -> f64 {
+        let pixels = self.touched(change);
+        let before = pixels.iter().map(|i| self.pixel_error(*i)).sum::<f64>();
+
+        for (entry, a, b) in &change.removed {
+            self.lift(*entry, *a, *b);
+        }
+        for (entry, a, b) in &change.added {
+            self.lay(*entry, change.color, *a, *b);
+        }
+
+        let after = pixels.iter().map(|i| self.pixel_error(*i)).sum::<f64>();
+        let delta = after - before;
+        self.error += delta;
+
+        delta
+    }
+
+    fn revert(&mut self, change: &Change) {
+        let pixels = self.touched(change);
+        let before = pixels.iter().map(|i| self.pixel_error(*i)).sum::<f64>();
+
+        for (entry, a, b) in &change.added {
+            self.lift(*entry, *a, *b);
+        }
+        for (entry, a, b) in &change.removed {
+            self.lay(*entry, change.color, *a, *b);
+        }
+
+        let after = pixels.iter().map(|i| self.pixel_error(*i)).sum::<f64>();
+        self.error += after - before;
+    }
+
+    /// Update the path once the change is kept.
+    fn commit(&mut self, change: Change) {
+        let path = &mut self.paths[change.color];
+
+        for (offset, nail) in change.nails.iter().enumerate() {
+            let entry = path[change.start + offset];
+            self.entries[entry] = self.entries[entry].map(|(color, _)| (color, *nail));
+        }
+        for entry in path.drain(change.start + change.nails.len()..change.end) {
+            self.entries[entry] = None;
+        }
+    }
+
+    /// Every on board pixel under the chords a change removes or adds, once.
+    fn touched(&mut self, change: &Change) -> Vec<usize> {
+        self.mark += 1;
+        let mut pixels = Vec::new();
+
+        for (_, a, b) in change.removed.iter().chain(&change.added) {
+            for (i, _) in self.chords.get(*a, *b).unwrap().iter() {
+                if self.on_board[i] && self.marks[i] != self.mark {
+                    self.marks[i] = self.mark;
+                    pixels.push(i);
+                }
+            }
+        }
+
+        pixels
+    }
+
+    /// Add a thread of `color` between two nails, in its place in the pattern.
+    fn lay(&mut self, entry: usize, color: usize, a: Nail, b: Nail) {
+        for (i, coverage) in self.chords.get(a, b).unwrap().iter() {
+            let layers = &mut self.layers[i];
+            let at = layers.partition_point(|layer| layer.entry < entry);
+            layers.insert(
+                at,
+                Layer {
+                    entry,
+                    color,
+                    alpha: self.opacity * coverage,
+                },
+            );
+        }
+    }
+
+    /// Take away the thread laid by [`Model::lay`] with the same `entry`.
+    fn lift(&mut self, entry: usize, a: Nail, b: Nail) {
+        for (i, _) in self.chords.get(a, b).unwrap().iter() {
+            let layers = &mut self.layers[i];
+            let at = layers.partition_point(|layer| layer.entry < entry);
+            layers.remove(at);
+        }
+    }
+
+    /// Distance from what the pixel shows to the target, scaled to RGB like
+    /// the blender's. Threads are drawn the way the art draws them, see
+    /// [`BlendMode`], over the background where they leave it uncovered.
+    fn pixel_error(&self, i: usize) -> f64 {
+        let mut mix = [0.0f32; 3];
+        let mut covered = 0.0f32;
+
+        for layer in &self.layers[i] {
+            let thread = self.palette[layer.color];
+            let (shown, added) = match self.blend_mode {
+                BlendMode::Cover => (1.0, layer.alpha.min(1.0 - covered).max(0.0)),
+                BlendMode::Stack => (1.0 - layer.alpha, layer.alpha),
+            };
+            mix = [0, 1, 2].map(|c| mix[c] * shown + thread[c] * added);
+            covered = covered * shown + added;
+        }
+
+        let shown = [0, 1, 2].map(|c| mix[c] + self.background[c] * (1.0 - covered));
+        let distance = self.color_distance;
+        (distance.between(distance.coordinates(Rgb(shown)), self.target[i]) * self.distance_scale)
+            as f64
+    }
+
+    fn pattern(&self) -> NailPattern {
+        self.entries.iter().flatten().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+    const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+    fn options(iterations: usize, temperature: f64) -> RefineOptions {
+        RefineOptions {
+            iterations,
+            temperature,
+            opacity: 1.0,
+            blend_mode: BlendMode::Cover,
+            background: WHITE,
+            color_distance: ColorDistance::Rgb,
+            seed: 0,
+        }
+    }
+
+    #[test]
+    fn test_removes_a_stray_thread() {
+        // a black line across the middle, between the nails at 3 and 9 o'clock
+        let board = Board::new(20.0, 8, 1.0).unwrap();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(20, 20, |_, y| {
+            if y == 10 {
+                BLACK
+            } else {
+                WHITE
+            }
+        }));
+        let [right, _, _, _, left, _, top, _] = [0, 1, 2, 3, 4, 5, 6, 7].map(|i| board.nails()[i]);
+        let pattern = vec![(BLACK, right), (BLACK, left), (BLACK, top)];
+
+        let (refined, report) = refine(&board, &img, &pattern, &options(200, 0.0));
+
+        assert!(report.error_after < report.error_before);
+        assert!(report.accepted > 0);
+        assert_eq!(&refined[..2], &pattern[..2]);
+        assert!(!refined.contains(&(BLACK, top)));
+    }
+
+    #[test]
+    fn test_models_thread_order() {
+        // red and black threads over the same chord, on a red line
+        let board = Board::new(20.0, 8, 1.0).unwrap();
+        let red = Rgb([255, 0, 0]);
+        let img =
+            DynamicImage::ImageRgb8(RgbImage::from_fn(
+                20,
+                20,
+                |_, y| {
+                    if y == 10 {
+                        red
+                    } else {
+                        WHITE
+                    }
+                },
+            ));
+        let [right, left] = [0, 4].map(|i| board.nails()[i]);
+        let red_first = vec![(red, right), (BLACK, right), (red, left), (BLACK, left)];
+        let black_first = vec![(red, right), (BLACK, right), (BLACK, left), (red, left)];
+
+        let error = |pattern: &NailPattern, blend_mode| {
+            let options = RefineOptions {
+                blend_mode,
+                ..options(0, 0.0)
+            };
+            refine(&board, &img, pattern, &options).1.error_before
+        };
+
+        // the first thread shows when covering, the last when stacking
+        assert!(error(&red_first, BlendMode::Cover) < error(&black_first, BlendMode::Cover));
+        assert!(error(&black_first, BlendMode::Stack) < error(&red_first, BlendMode::Stack));
+        assert!(
+            (error(&red_first, BlendMode::Cover) - error(&black_first, BlendMode::Stack)).abs()
+                < 1e-9
+        );
+    }
+
+    #[test]
+    fn test_paths_stay_continuous() {
+        let board = Board::new(30.0, 12, 1.0).unwrap();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(30, 30, |x, y| {
+            Rgb([(x * 8) as u8, (y * 8) as u8, 128])
+        }));
+        let red = Rgb([255, 0, 0]);
+        let nails = board.nails();
+        let pattern = (0..40)
+            .map(|i| {
+                let color = if i % 2 == 0 { red } else { BLACK };
+                (color, nails[(i * 5 + i / 2) % nails.len()])
+            })
+            .collect::<NailPattern>();
+
+        for temperature in [0.0, 1.0] {
+            let (refined, report) = refine(&board, &img, &pattern, &options(500, temperature));
+            assert!(report.error_after <= report.error_before || temperature > 0.0);
+
+            for color in [red, BLACK] {
+                let path = refined
+                    .iter()
+                    .filter(|(c, _)| *c == color)
+                    .map(|(_, nail)| *nail)
+                    .collect::<Vec<_>>();
+                assert!(path
+                    .windows(2)
+                    .all(|w| board.chords().get(w[0], w[1]).is_some()));
+            }
+
+            // the error reported is the error of the pattern handed back
+            let (_, again) = refine(&board, &img, &refined, &options(0, 0.0));
+            assert!((again.error_before - report.error_after).abs() < 1e-6);
+        }
+    }
+}
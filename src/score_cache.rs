@@ -0,0 +1,155 @@
+use rayon::prelude::*;
+
+use crate::chords::Chords;
+
+/// Incremental updates leave rounding errors this small behind, far less than
+/// any chord actually matches.
+pub const ROUNDING: f64 = 1e-6;
+
+/// How much of the uncovered part of the image every chord would cover, in
+/// total and for each color, kept up to date as pixels get covered instead of
+/// rescanning every chord on every step.
+///
+/// Pixels have a color, an index below `color_count` or anything else for none,
+/// and how much of them is left uncovered. A chord covers the smaller of its
+/// coverage and what is left of each pixel. Whenever a pixel changes, only the
+/// chords crossing it are updated, found through an index from every pixel to
+/// the chords that cross it.
+pub struct ScoreCache {
+    color_count: usize,
+    totals: Vec<f64>,
+    /// `color_count` entries for every chord
+    matches: Vec<f64>,
+    /// Where the chords crossing every pixel start in `crossings`, followed by
+    /// the end of the last pixel
+    offsets: Vec<usize>,
+    crossings: Vec<u32>,
+    coverage: Option<Vec<f32>>,
+}
+
+impl ScoreCache {
+    pub fn new(chords: &Chords, colors: &[u8], left: &[f32], color_count: usize) -> Self {
+        assert_eq!(colors.len(), left.len());
+
+        let scores = (0..chords.pair_count())
+            .into_par_iter()
+            .map(|pair| {
+                let mut total = 0.0;
+                let mut matches = vec![0.0; color_count];
+
+                for (i, coverage) in chords.chord_at(pair).into_iter().flat_map(|c| c.iter()) {
+                    let covered = coverage.min(left[i]) as f64;
+                    total += covered;
+                    if let Some(matched) = matches.get_mut(colors[i] as usize) {
+                        *matched += covered;
+                    }
+                }
+
+                (total, matches)
+            })
+            .collect::<Vec<_>>();
+
+        let mut totals = Vec::with_capacity(scores.len());
+        let mut matches = Vec::with_capacity(scores.len() * color_count);
+        for (total, pair_matches) in scores {
+            totals.push(total);
+            matches.extend(pair_matches);
+        }
+
+        // count the chords crossing every pixel, then fill them in
+        let mut offsets = vec![0; left.len() + 1];
+        for pair in 0..chords.pair_count() {
+            for (i, _) in chords.chord_at(pair).into_iter().flat_map(|c| c.iter()) {
+                offsets[i + 1] += 1;
+            }
+        }
+        for i in 1..offsets.len() {
+            offsets[i] += offsets[i - 1];
+        }
+
+        let mut next = offsets.clone();
+        let mut crossings = vec![0; offsets[left.len()]];
+        let mut coverage = chords
+            .partial_coverage()
+            .then(|| vec![0.0; crossings.len()]);
+        for pair in 0..chords.pair_count() {
+            for (i, c) in chords.chord_at(pair).into_iter().flat_map(|c| c.iter()) {
+                crossings[next[i]] = pair as u32;
+                if let Some(coverage) = &mut coverage {
+                    coverage[next[i]] = c;
+                }
+                next[i] += 1;
+            }
+        }
+
+        Self {
+            color_count,
+            totals,
+            matches,
+            offsets,
+            crossings,
+            coverage,
+        }
+    }
+
+    /// How much the chord with index `pair` covers of the pixels of `color`,
+    /// and that minus how much it covers of pixels of other colors.
+    pub fn score(&self, pair: usize, color: usize) -> (f64, f64) {
+        let matched = self.matches[pair * self.color_count + color];
+        (matched, 2.0 * matched - self.totals[pair])
+    }
+
+    /// Pixel `i` of `color` went from `old` to `new` left uncovered.
+    pub fn update(&mut self, i: usize, color: u8, old: f32, new: f32) {
+        let color = color as usize;
+
+        for n in self.offsets[i]..self.offsets[i + 1] {
+            let coverage = self.coverage.as_ref().map_or(1.0, |c| c[n]);
+            let change = (coverage.min(new) - coverage.min(old)) as f64;
+            let pair = self.crossings[n] as usize;
+
+            self.totals[pair] += change;
+            if color < self.color_count {
+                self.matches[pair * self.color_count + color] += change;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Board;
+
+    #[test]
+    fn test_updates_match_a_fresh_cache() {
+        let board = Board::new(20.0, 12, 1.0)
+            .unwrap()
+            .with_thread_width(0.7)
+            .unwrap();
+        let chords = board.chords();
+        let pixels = 20 * 20;
+        let colors = (0..pixels).map(|i| (i % 7 % 3) as u8).collect::<Vec<_>>();
+        let mut left = vec![1.0; pixels];
+
+        let mut cache = ScoreCache::new(chords, &colors, &left, 2);
+        for (from, to) in [(0, 5), (3, 9), (5, 11)] {
+            let nails = board.nails();
+            for (i, coverage) in chords.get(nails[from], nails[to]).unwrap().iter() {
+                let old = left[i];
+                left[i] = (old - coverage).max(0.0);
+                cache.update(i, colors[i], old, left[i]);
+            }
+        }
+
+        let fresh = ScoreCache::new(chords, &colors, &left, 2);
+        for pair in 0..chords.pair_count() {
+            for color in 0..2 {
+                let (matched, score) = cache.score(pair, color);
+                let (fresh_matched, fresh_score) = fresh.score(pair, color);
+                assert!((matched - fresh_matched).abs() < ROUNDING);
+                assert!((score - fresh_score).abs() < ROUNDING);
+            }
+        }
+    }
+}
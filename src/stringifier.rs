@@ -1,74 +1,142 @@
 use crate::art_algo::{ArtAlgo, StrandPositions};
-use crate::board::NailNailPaths;
+use crate::chords::Chords;
+use crate::score_cache::{ScoreCache, ROUNDING};
 use crate::util::Dimensions;
 use crate::{
     board::{Board, Nail},
-    image_utils::dither_image,
+    image_utils::{dither_image, ColorDistance},
     util::ColorPalette,
 };
-use image::{DynamicImage, GenericImageView, Rgb};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
-use std::thread;
-use threadpool::ThreadPool;
+use image::{DynamicImage, Rgb};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::HashMap, sync::Arc};
 
 pub struct Stringifier {
     initial_nails: HashMap<Rgb<u8>, Nail>,
-    paths: HashMap<Nail, HashMap<Nail, Arc<Vec<(u32, u32)>>>>,
-    remaining_pixels: Arc<RwLock<HashMap<Xy, Rgb<u8>>>>,
+    palette: Vec<Rgb<u8>>,
+    chords: Arc<Chords>,
+    /// Score penalty of every chord, by pair index
+    penalties: Vec<f64>,
+    remaining_pixels: RemainingPixels,
+    scores: ScoreCache,
     dimensions: Dimensions,
 }
 
-type Xy = (u32, u32);
+/// Marks pixels whose color isn't in the palette, which every thread mismatches.
+const NO_COLOR: u8 = u8::MAX;
 
-impl Stringifier {
-    pub fn new(board: &Board, src_img: &DynamicImage, color_palette: ColorPalette) -> Self {
-        let scaled_img = board.scale_image(src_img, None);
-        let dithered_img = dither_image(&scaled_img, color_palette);
+/// Most thread colors the stringifier takes, leaving one index for [`NO_COLOR`].
+pub const MAX_COLORS: usize = NO_COLOR as usize;
 
-        let initial_nails =
-            Stringifier::starting_nails(board.nails(), board.paths(), color_palette, &dithered_img);
+/// Every pixel still to be covered, row major: the palette index of its color
+/// and how much of it is left uncovered. Covered pixels and pixels off the
+/// board have nothing left.
+#[derive(Debug, Clone, PartialEq)]
+struct RemainingPixels {
+    width: u32,
+    colors: Vec<u8>,
+    left: Vec<f32>,
+}
 
-        let remaining_pixels = Stringifier::image_to_pixel_options(&dithered_img);
-        let remaining_pixels = Arc::new(RwLock::new(remaining_pixels));
+impl RemainingPixels {
+    /// All of `image` uncovered, with colors looked up in `palette`.
+    fn new(image: &DynamicImage, palette: ColorPalette) -> Self {
+        assert!(palette.len() <= MAX_COLORS);
 
-        let paths = board.paths().clone();
-        let paths = convert_to_arc_paths(paths);
+        let rgb_img = image.to_rgb8();
+        let colors = rgb_img
+            .pixels()
+            .map(|pixel| {
+                palette
+                    .iter()
+                    .position(|color| color == pixel)
+                    .map_or(NO_COLOR, |i| i as u8)
+            })
+            .collect::<Vec<_>>();
 
         Self {
-            initial_nails,
-            paths,
-            remaining_pixels,
-            dimensions: board.dimensions().clone(),
+            width: rgb_img.width(),
+            left: vec![1.0; colors.len()],
+            colors,
         }
     }
+}
 
-    fn image_to_pixel_options(image: &DynamicImage) -> HashMap<Xy, Rgb<u8>> {
-        let rgb_img = image.to_rgb8();
-        let mut pixels = HashMap::new();
-        let (width, height) = rgb_img.dimensions();
+/// See [`ArtAlgo::save_state`]. How much of every pixel is left uncovered,
+/// row major. The colors come from dithering the source image again.
+#[derive(Serialize, Deserialize)]
+struct StringifierState {
+    left: Vec<f32>,
+}
 
-        for x in 0..width {
-            for y in 0..height {
-                let pixel_color = rgb_img.get_pixel(x, y);
-                pixels.insert((x, y), *pixel_color);
+impl Stringifier {
+    /// Dithers `src_img` to `color_palette`, with pixels going to the closest
+    /// color by `color_distance`, and scores chords by how many pixels of
+    /// their color they cover.
+    pub fn new(
+        board: &Board,
+        src_img: &DynamicImage,
+        color_palette: ColorPalette,
+        color_distance: ColorDistance,
+    ) -> Self {
+        let scaled_img = board.scale_image(src_img, None);
+        let dithered_img = dither_image(&scaled_img, color_palette, color_distance);
+
+        let mut remaining_pixels = RemainingPixels::new(&dithered_img, color_palette);
+        let width = remaining_pixels.width;
+        for (i, left) in remaining_pixels.left.iter_mut().enumerate() {
+            if !board.contains_pixel(i as u32 % width, i as u32 / width) {
+                *left = 0.0;
             }
         }
 
-        pixels
+        let chords = Arc::clone(board.chords());
+        let penalties = chord_penalties(board, &chords);
+
+        Stringifier::with_pixels(
+            chords,
+            color_palette,
+            penalties,
+            remaining_pixels,
+            *board.dimensions(),
+        )
+    }
+
+    fn with_pixels(
+        chords: Arc<Chords>,
+        color_palette: ColorPalette,
+        penalties: Vec<f64>,
+        remaining_pixels: RemainingPixels,
+        dimensions: Dimensions,
+    ) -> Self {
+        let scores = ScoreCache::new(
+            &chords,
+            &remaining_pixels.colors,
+            &remaining_pixels.left,
+            color_palette.len(),
+        );
+
+        Self {
+            initial_nails: Stringifier::starting_nails(&chords, &scores, color_palette),
+            palette: color_palette.to_vec(),
+            chords,
+            penalties,
+            remaining_pixels,
+            scores,
+            dimensions,
+        }
     }
 
     fn starting_nails(
-        nails: &Vec<Nail>,
-        paths: &NailNailPaths,
+        chords: &Chords,
+        scores: &ScoreCache,
         color_palette: ColorPalette,
-        dithered_img: &DynamicImage,
     ) -> HashMap<Rgb<u8>, Nail> {
         let mut starting_nails = HashMap::new();
-        let dithered_rgb = dithered_img.to_rgb8();
 
-        for color in color_palette {
-            let chosen_path = Stringifier::choose_path(nails, paths, &dithered_rgb, color);
+        for (i, color) in color_palette.iter().enumerate() {
+            let chosen_path = Stringifier::choose_path(chords, scores, i);
 
             if let Some(nails) = chosen_path {
                 starting_nails.insert(*color, nails.0);
@@ -77,28 +145,19 @@ impl Stringifier {
         starting_nails
     }
 
-    fn choose_path(
-        nails: &Vec<Nail>,
-        paths: &NailNailPaths,
-        dithered_rgb: &image::ImageBuffer<Rgb<u8>, Vec<u8>>,
-        color: &Rgb<u8>,
-    ) -> Option<(Nail, Nail)> {
-        let mut max_match = 0;
+    fn choose_path(chords: &Chords, scores: &ScoreCache, color: usize) -> Option<(Nail, Nail)> {
+        let mut max_match = 0.0;
         let mut chosen_path = None;
+        let nails = chords.nails();
 
         for i in 0..nails.len() {
             for j in i + 1..nails.len() {
                 let start = nails[i];
                 let end = nails[j];
-                let path = paths.get(&start).unwrap().get(&end).unwrap();
-                let mut match_count = 0;
-
-                for (x, y) in path {
-                    let pixel_color = dithered_rgb.get_pixel(*x, *y);
-                    if pixel_color == color {
-                        match_count += 1;
-                    }
-                }
+                let Some(pair) = chords.pair(start, end) else {
+                    continue;
+                };
+                let (match_count, _) = scores.score(pair, color);
 
                 if match_count > max_match {
                     max_match = match_count;
@@ -109,165 +168,229 @@ impl Stringifier {
         chosen_path
     }
 
-    fn clear_path(&mut self, from_nail: Nail, to_nail: Nail) {
-        let path = self.paths.get(&from_nail).unwrap().get(&to_nail).unwrap();
+    /// Every move worth making from `nails`: a thread covering at least some
+    /// of its color without more than a row's worth of loss. In palette and
+    /// board order.
+    pub(crate) fn moves<'a>(
+        &'a self,
+        nails: &'a StrandPositions,
+    ) -> impl Iterator<Item = Move> + 'a {
+        self.starts(nails)
+            .flat_map(move |(color_index, color, nail)| {
+                self.chords
+                    .pairs_from(nail)
+                    .filter_map(move |(next_nail, pair)| {
+                        self.scored_move(color_index, color, nail, next_nail, pair)
+                    })
+            })
+    }
 
-        path.iter().for_each(|(x, y)| {
-            let mut rp = self.remaining_pixels.write().unwrap();
-            rp.remove(&(*x, *y));
-        });
+    /// [`Stringifier::moves`] scored in parallel, along with the palette index
+    /// of their color.
+    fn par_moves<'a>(
+        &'a self,
+        nails: &'a StrandPositions,
+    ) -> impl ParallelIterator<Item = (usize, Move)> + 'a {
+        self.starts(nails)
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .flat_map(move |(color_index, color, nail)| {
+                self.chords
+                    .par_pairs_from(nail)
+                    .filter_map(move |(next_nail, pair)| {
+                        let candidate =
+                            self.scored_move(color_index, color, nail, next_nail, pair)?;
+                        Some((color_index, candidate))
+                    })
+            })
     }
-}
 
-fn convert_to_arc_paths(
-    paths: HashMap<Nail, HashMap<Nail, Vec<(u32, u32)>>>,
-) -> HashMap<Nail, HashMap<Nail, Arc<Vec<(u32, u32)>>>> {
-    let paths = paths
-        .iter()
-        .map(|(from_nail, paths_from)| {
-            let paths_from = paths_from
-                .iter()
-                .map(|(end, path)| (end.clone(), Arc::new(path.clone())))
-                .collect::<HashMap<Nail, Arc<Vec<(u32, u32)>>>>();
-            (*from_nail, paths_from)
-        })
-        .collect::<HashMap<Nail, HashMap<Nail, Arc<Vec<(u32, u32)>>>>>();
-    paths
-}
+    /// The better of two moves given with the palette index of their color.
+    /// Scores kept up to date step by step can be off by rounding, so they
+    /// are compared in steps of [`ROUNDING`], and equal moves go to the
+    /// lowest color index and then the lowest nail index. That is a total
+    /// order, so the pick is the same however the moves are split between
+    /// workers.
+    fn better_move(&self, a: (usize, Move), b: (usize, Move)) -> (usize, Move) {
+        let ((a_color, a_move), (b_color, b_move)) = (a, b);
+        let quantized = |score: f64| (score / ROUNDING).round() as i64;
+
+        match quantized(a_move.score).cmp(&quantized(b_move.score)) {
+            Ordering::Greater => a,
+            Ordering::Less => b,
+            Ordering::Equal => {
+                let a_key = (a_color, self.chords.index(a_move.to));
+                let b_key = (b_color, self.chords.index(b_move.to));
+                if a_key <= b_key {
+                    a
+                } else {
+                    b
+                }
+            }
+        }
+    }
 
-impl ArtAlgo for Stringifier {
-    fn initial_nails(&self) -> HashMap<Rgb<u8>, Nail> {
-        self.initial_nails.clone()
+    /// The palette index, color and current nail of every thread, in palette
+    /// order.
+    fn starts<'a>(
+        &'a self,
+        nails: &'a StrandPositions,
+    ) -> impl Iterator<Item = (usize, Rgb<u8>, Nail)> + 'a {
+        self.palette
+            .iter()
+            .enumerate()
+            .filter_map(|(color_index, color)| Some((color_index, *color, *nails.get(color)?)))
     }
 
-    fn next_nail(&mut self, nails: &StrandPositions) -> Option<(Rgb<u8>, Nail)> {
-        let pool = ThreadPool::new(num_cpus::get());
+    /// The move along chord `pair`, if it is worth making, see
+    /// [`Stringifier::moves`].
+    fn scored_move(
+        &self,
+        color_index: usize,
+        color: Rgb<u8>,
+        from: Nail,
+        to: Nail,
+        pair: usize,
+    ) -> Option<Move> {
+        let worst_possible_score = -(self.dimensions.width() as f64);
+        let (match_count, score) = self.scores.score(pair, color_index);
+        let score = score - self.penalties[pair];
+
+        (match_count > ROUNDING && score > worst_possible_score).then_some(Move {
+            color,
+            from,
+            to,
+            score,
+        })
+    }
 
-        let worst_possible_score = -(self.dimensions.width() as i32);
-        let best_move = None;
-        let best_score = worst_possible_score;
-        let best_move: Arc<Mutex<Option<(Rgb<u8>, Nail)>>> = Arc::new(Mutex::new(best_move));
-        let best_score = Arc::new(Mutex::new(best_score));
+    /// Cover the pixels of a chord, updating the scores of the chords
+    /// crossing the ones that changed. Returns what the changed pixels had
+    /// left before, see [`Stringifier::uncover`].
+    pub(crate) fn cover(&mut self, from_nail: Nail, to_nail: Nail) -> Vec<(usize, f32)> {
+        let chord = self.chords.get(from_nail, to_nail).unwrap();
 
-        let handles: Vec<_> = nails
-            .iter()
-            .flat_map(|(color, nail)| {
-                let paths_from_nail = self.paths.get(nail).unwrap();
+        let rp = &mut self.remaining_pixels;
+        let mut changes = Vec::with_capacity(chord.len());
 
-                paths_from_nail
-                    .iter()
-                    .map(|(next_nail, path)| {
-                        try_move(
-                            &pool,
-                            Arc::clone(&self.remaining_pixels),
-                            Arc::clone(path),
-                            *color,
-                            Arc::clone(&best_move),
-                            Arc::clone(&best_score),
-                            *next_nail,
-                        )
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect();
+        chord.iter().for_each(|(i, coverage)| {
+            let old = rp.left[i];
+            rp.left[i] -= coverage;
+            if rp.left[i] <= f32::EPSILON {
+                rp.left[i] = 0.0;
+            }
 
-        pool.join();
+            if rp.left[i] != old {
+                self.scores.update(i, rp.colors[i], old, rp.left[i]);
+                changes.push((i, old));
+            }
+        });
 
-        let best_move = best_move.lock().unwrap().clone();
+        changes
+    }
 
-        if let Some((color, next_nail)) = best_move {
-            self.clear_path(nails[&color], next_nail);
-        }
+    /// Undo [`Stringifier::cover`], most recent first.
+    pub(crate) fn uncover(&mut self, changes: Vec<(usize, f32)>) {
+        let rp = &mut self.remaining_pixels;
 
-        best_move
+        for (i, old) in changes.into_iter().rev() {
+            self.scores.update(i, rp.colors[i], rp.left[i], old);
+            rp.left[i] = old;
+        }
     }
 }
 
-fn try_move(
-    pool: &ThreadPool,
-    remaining_pixels: Arc<RwLock<HashMap<(u32, u32), Rgb<u8>>>>,
-    path: Arc<Vec<(u32, u32)>>,
-    color: Rgb<u8>,
-    best_move: Arc<Mutex<Option<(Rgb<u8>, Nail)>>>,
-    best_score: Arc<Mutex<i32>>,
-    next_nail: Nail,
-) {
-    pool.execute(move || {
-        let remaining_pixels = remaining_pixels.read().unwrap();
-
-        let (match_count, score) = path_score(path, remaining_pixels, color);
-
-        let mut best_move = best_move.lock().unwrap();
-        let mut best_score = best_score.lock().unwrap();
-
-        if match_count > 0 && score > *best_score {
-            *best_score = score;
-            *best_move = Some((color, next_nail));
+/// A thread of `color` from `from` to `to` and the score its chord gets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Move {
+    pub color: Rgb<u8>,
+    pub from: Nail,
+    pub to: Nail,
+    pub score: f64,
+}
+
+fn chord_penalties(board: &Board, chords: &Chords) -> Vec<f64> {
+    let mut penalties = vec![0.0; chords.pair_count()];
+
+    for from in chords.nails() {
+        for (to, pair) in chords.pairs_from(*from) {
+            penalties[pair] = board.chord_penalty(*from, to) as f64;
         }
-    })
+    }
+
+    penalties
 }
 
-fn path_score(
-    path: Arc<Vec<(u32, u32)>>,
-    remaining_pixels: std::sync::RwLockReadGuard<HashMap<(u32, u32), Rgb<u8>>>,
-    color: Rgb<u8>,
-) -> (i32, i32) {
-    let mut match_count = 0;
-    let mut mismatch_count = 0;
+impl ArtAlgo for Stringifier {
+    fn initial_nails(&self) -> HashMap<Rgb<u8>, Nail> {
+        self.initial_nails.clone()
+    }
 
-    path.iter().for_each(|(x, y)| {
-        let pixel_color = remaining_pixels.get(&(*x, *y));
+    fn palette(&self) -> Vec<Rgb<u8>> {
+        self.palette.clone()
+    }
 
-        match pixel_color {
-            Some(pixel_color) if *pixel_color == color => {
-                match_count += 1;
-            }
-            Some(_) => {
-                mismatch_count += 1;
-            }
-            None => (),
+    fn next_nail(&mut self, nails: &StrandPositions) -> Option<(Rgb<u8>, Nail)> {
+        // every worker keeps its best move, merged into one at the end
+        let (_, best_move) = self
+            .par_moves(nails)
+            .reduce_with(|a, b| self.better_move(a, b))?;
+
+        self.cover(best_move.from, best_move.to);
+
+        Some((best_move.color, best_move.to))
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::to_value(StringifierState {
+            left: self.remaining_pixels.left.clone(),
+        })
+        .unwrap()
+    }
+
+    fn restore_state(
+        &mut self,
+        state: serde_json::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let state: StringifierState = serde_json::from_value(state)?;
+
+        if state.left.len() != self.remaining_pixels.left.len() {
+            return Err(format!(
+                "state has {} pixels, the board has {}",
+                state.left.len(),
+                self.remaining_pixels.left.len()
+            )
+            .into());
         }
-    });
 
-    let score = match_count - mismatch_count;
-    (match_count, score)
+        self.remaining_pixels.left = state.left;
+        self.scores = ScoreCache::new(
+            &self.chords,
+            &self.remaining_pixels.colors,
+            &self.remaining_pixels.left,
+            self.palette.len(),
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use image::DynamicImage;
     use image::RgbImage;
-    use std::sync::{Arc, Mutex};
-    use std::thread;
-
-    use super::*;
 
     // NWWWN
     // W  W
     // G B
     // GG
     // N
-    fn create_mock_board() -> (Vec<Nail>, NailNailPaths, DynamicImage) {
-        let nails = vec![Nail(0, 0), Nail(0, 4), Nail(4, 0)];
-        let mut paths = HashMap::new();
-
-        let mut from00 = HashMap::new();
-        from00.insert(Nail(0, 4), vec![(0, 1), (0, 2), (0, 3)]);
-        from00.insert(Nail(4, 0), vec![(1, 0), (2, 0), (3, 0)]);
-
-        let mut from04 = HashMap::new();
-        from04.insert(Nail(0, 0), vec![(0, 1), (0, 2), (0, 3)]);
-        from04.insert(Nail(4, 0), vec![(1, 3), (2, 2), (3, 1)]);
-
-        let mut from40 = HashMap::new();
-        from40.insert(Nail(0, 0), vec![(1, 0), (2, 0), (3, 0)]);
-        from40.insert(Nail(0, 4), vec![(1, 3), (2, 2), (3, 1)]);
-
-        paths.insert(Nail(0, 0), from00);
-        paths.insert(Nail(0, 4), from04);
-        paths.insert(Nail(4, 0), from40);
+    fn create_mock_board() -> (Arc<Chords>, DynamicImage) {
+        (create_mock_chords(1.0), create_mock_image())
+    }
 
-        let img: DynamicImage = DynamicImage::ImageRgb8(RgbImage::from_fn(5, 5, |x, y| {
+    fn create_mock_image() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(5, 5, |x, y| {
             if y < 2 {
                 return Rgb([255, 255, 255]);
             }
@@ -275,24 +398,46 @@ mod tests {
                 return Rgb([127, 127, 127]);
             }
             Rgb([0, 0, 0])
-        }));
+        }))
+    }
 
-        (nails, paths, img)
+    /// The chords of the mock board, the top one covering `top` of its pixels.
+    fn create_mock_chords(top: f32) -> Arc<Chords> {
+        let nails = vec![Nail(0, 0), Nail(0, 4), Nail(4, 0)];
+        let chords = Chords::new(&nails, &Dimensions::new(5, 5), |i, j| match (i, j) {
+            (0, 1) => vec![(0, 1, 1.0), (0, 2, 1.0), (0, 3, 1.0)],
+            (0, 2) => vec![(1, 0, top), (2, 0, top), (3, 0, top)],
+            _ => vec![(1, 3, 1.0), (2, 2, 1.0), (3, 1, 1.0)],
+        });
+
+        Arc::new(chords)
+    }
+
+    fn mock_stringifier(
+        chords: Arc<Chords>,
+        img: &DynamicImage,
+        palette: ColorPalette,
+    ) -> Stringifier {
+        let penalties = vec![0.0; chords.pair_count()];
+        let remaining_pixels = RemainingPixels::new(img, palette);
+
+        Stringifier::with_pixels(
+            chords,
+            palette,
+            penalties,
+            remaining_pixels,
+            Dimensions::new(5, 5),
+        )
     }
 
     #[test]
     fn test_choose_next_nail() {
-        let (_nails, paths, img) = create_mock_board();
+        let (chords, img) = create_mock_board();
         let color = Rgb([255, 255, 255]);
 
         let current_nails = HashMap::from([(color, Nail(0, 0))]);
 
-        let mut stringifier = Stringifier {
-            initial_nails: current_nails.clone(),
-            paths: convert_to_arc_paths(paths),
-            remaining_pixels: Arc::new(RwLock::new(Stringifier::image_to_pixel_options(&img))),
-            dimensions: Dimensions::new(5, 5),
-        };
+        let mut stringifier = mock_stringifier(chords, &img, &[color]);
 
         let next_nail = stringifier
             .next_nail(&current_nails)
@@ -303,7 +448,7 @@ mod tests {
 
     #[test]
     fn choose_two_nails() {
-        let (_nails, paths, img) = create_mock_board();
+        let (chords, img) = create_mock_board();
 
         let w = Rgb([255, 255, 255]);
         let g = Rgb([127, 127, 127]);
@@ -311,12 +456,7 @@ mod tests {
 
         let mut current_nails = HashMap::from([(w, Nail(0, 0)), (g, Nail(0, 0)), (b, Nail(0, 0))]);
 
-        let mut stringifier = Stringifier {
-            initial_nails: current_nails.clone(),
-            paths: convert_to_arc_paths(paths),
-            remaining_pixels: Arc::new(RwLock::new(Stringifier::image_to_pixel_options(&img))),
-            dimensions: Dimensions::new(5, 5),
-        };
+        let mut stringifier = mock_stringifier(chords, &img, &[w, g, b]);
 
         let next_nail = stringifier
             .next_nail(&current_nails)
@@ -331,13 +471,186 @@ mod tests {
         assert_eq!(next_nail, (g, Nail(0, 4)));
     }
 
+    #[test]
+    fn test_partial_coverage() {
+        let chords = create_mock_chords(0.5);
+        let w = Rgb([255, 255, 255]);
+
+        let mut stringifier = mock_stringifier(chords, &create_mock_image(), &[w]);
+
+        stringifier.cover(Nail(0, 0), Nail(4, 0));
+        let remaining_pixels = &stringifier.remaining_pixels;
+        assert_eq!(remaining_pixels.left[2], 0.5);
+        assert_eq!(remaining_pixels.colors[2], 0);
+        assert_eq!(remaining_pixels.colors[3 * 5 + 2], NO_COLOR);
+
+        // the half covered pixels still count for half a pixel each
+        let top = stringifier.chords.pair(Nail(0, 0), Nail(4, 0)).unwrap();
+        assert_eq!(stringifier.scores.score(top, 0), (1.5, 1.5));
+
+        // the left edge has one white pixel and two of no palette color
+        let left = stringifier.chords.pair(Nail(0, 0), Nail(0, 4)).unwrap();
+        assert_eq!(stringifier.scores.score(left, 0), (1.0, -1.0));
+    }
+
+    #[test]
+    fn test_ties_go_to_lowest_index() {
+        let chords = create_mock_chords(1.0);
+        let w = Rgb([255, 255, 255]);
+        let g = Rgb([127, 127, 127]);
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(5, 5, w));
+
+        // every chord from the corner covers 3 white pixels
+        let current_nails = HashMap::from([(g, Nail(0, 0)), (w, Nail(0, 0))]);
+
+        for _ in 0..10 {
+            let mut stringifier = mock_stringifier(Arc::clone(&chords), &img, &[g, w]);
+
+            assert_eq!(stringifier.next_nail(&current_nails), Some((w, Nail(0, 4))));
+        }
+    }
+
+    #[test]
+    fn test_runs_are_reproducible() {
+        let board = Board::new(40.0, 24, 1.0).unwrap();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 40, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+        let palette = [Rgb([0, 0, 0]), Rgb([255, 255, 255])];
+
+        let run = || {
+            let board = std::rc::Rc::new(Board::new(40.0, 24, 1.0).unwrap());
+            let algo = Box::new(Stringifier::new(&board, &img, &palette, ColorDistance::Rgb));
+            let mut generator = crate::ArtGenerator::new(board, algo);
+            for _ in 0..50 {
+                generator.step();
+            }
+            generator.pattern().clone()
+        };
+
+        let pattern = run();
+        assert!(pattern.len() > 10);
+        assert!(board.nails().contains(&pattern[0].1));
+        assert_eq!(run(), pattern);
+    }
+
+    #[test]
+    fn test_same_moves_on_any_thread_count() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 40, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+        let palette = [Rgb([0, 0, 0]), Rgb([255, 255, 255])];
+
+        let run = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let board = std::rc::Rc::new(Board::new(40.0, 24, 1.0).unwrap());
+                let algo = Box::new(Stringifier::new(&board, &img, &palette, ColorDistance::Rgb));
+                let mut generator = crate::ArtGenerator::new(board, algo);
+                for _ in 0..50 {
+                    generator.step();
+                }
+                generator.pattern().clone()
+            })
+        };
+
+        let pattern = run(1);
+        for threads in [2, 4, 7] {
+            assert_eq!(run(threads), pattern);
+        }
+    }
+
+    #[test]
+    fn test_threads_start_in_palette_order() {
+        let board = std::rc::Rc::new(Board::new(40.0, 24, 1.0).unwrap());
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 40, |x, _| {
+            if x < 20 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+        let palette = [Rgb([255, 255, 255]), Rgb([0, 0, 0])];
+
+        let algo = Box::new(Stringifier::new(&board, &img, &palette, ColorDistance::Rgb));
+        let generator = crate::ArtGenerator::new(board, algo);
+
+        let colors = generator.pattern().iter().map(|(color, _)| *color);
+        assert_eq!(colors.collect::<Vec<_>>(), palette);
+    }
+
+    #[test]
+    fn test_save_and_restore_state() {
+        let (chords, img) = create_mock_board();
+        let w = Rgb([255, 255, 255]);
+        let current_nails = HashMap::from([(w, Nail(0, 0))]);
+
+        let new_stringifier = || mock_stringifier(Arc::clone(&chords), &img, &[w]);
+
+        let mut stringifier = new_stringifier();
+        stringifier.next_nail(&current_nails);
+        let state = stringifier.save_state();
+
+        let mut resumed = new_stringifier();
+        resumed.restore_state(state).unwrap();
+        assert_eq!(resumed.remaining_pixels, stringifier.remaining_pixels);
+        assert_eq!(resumed.remaining_pixels.left[2], 0.0);
+
+        let too_small = serde_json::json!({ "left": [1.0] });
+        assert!(resumed.restore_state(too_small).is_err());
+    }
+
     #[test]
     fn test_choose_path() {
-        let (nails, paths, img) = create_mock_board();
+        let (chords, img) = create_mock_board();
         let color = Rgb([255, 255, 255]);
 
-        let chosen_path = Stringifier::choose_path(&nails, &paths, &img.to_rgb8(), &color);
+        let pixels = RemainingPixels::new(&img, &[color]);
+        let scores = ScoreCache::new(&chords, &pixels.colors, &pixels.left, 1);
+        let chosen_path = Stringifier::choose_path(&chords, &scores, 0);
 
         assert_eq!(chosen_path, Some((Nail(0, 0), Nail(4, 0))));
     }
+
+    #[test]
+    fn test_ties_within_rounding() {
+        let nails = vec![Nail(0, 0), Nail(1, 0), Nail(2, 0), Nail(3, 0), Nail(4, 0)];
+        let chords = Chords::new(&nails, &Dimensions::new(6, 1), |i, j| match (i, j) {
+            // a hair less than two pixels once the pixel at 2 is covered
+            (0, 1) => vec![(0, 0, 1.0), (1, 0, 1.0 - f32::EPSILON), (2, 0, 1.0)],
+            (0, 2) => vec![(3, 0, 1.0), (4, 0, 1.0)],
+            (3, 4) => vec![(2, 0, 0.5), (5, 0, 1.0)],
+            _ => Vec::new(),
+        });
+        let white = Rgb([255, 255, 255]);
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(6, 1, white));
+
+        let current_nails = HashMap::from([(white, Nail(0, 0))]);
+
+        // the pixel at 2 is covered in two halves
+        let mut stringifier = mock_stringifier(Arc::new(chords), &img, &[white]);
+        for _ in 0..2 {
+            stringifier.cover(Nail(3, 0), Nail(4, 0));
+        }
+        let scores = stringifier
+            .moves(&current_nails)
+            .map(|m| m.score)
+            .collect::<Vec<_>>();
+        assert!(scores[0] < scores[1] && scores[1] - scores[0] < ROUNDING);
+        assert_eq!(
+            stringifier.next_nail(&current_nails),
+            Some((white, Nail(1, 0)))
+        );
+    }
 }
@@ -21,3 +21,42 @@ impl Dimensions {
         self.height
     }
 }
+
+/// Color with channels from 0 to 1, for mixing threads.
+pub(crate) type Color = [f32; 3];
+
+pub(crate) fn to_color(color: &Rgb<u8>) -> Color {
+    color.0.map(|channel| channel as f32 / 255.0)
+}
+
+pub fn parse_hex_color(s: &str) -> Result<Rgb<u8>, String> {
+    let hex = s.trim().trim_start_matches('#');
+
+    // from_str_radix would take a leading + as well
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a 6 digit hex color", s));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+
+    Ok(Rgb([channel(0), channel(2), channel(4)]))
+}
+
+pub fn to_hex_color(color: &Rgb<u8>) -> String {
+    format!("{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#d6babd"), Ok(Rgb([0xd6, 0xba, 0xbd])));
+        assert_eq!(parse_hex_color(" 140917 "), Ok(Rgb([0x14, 0x09, 0x17])));
+        assert!(parse_hex_color("12345").is_err());
+        assert!(parse_hex_color("12345g").is_err());
+        assert!(parse_hex_color("a€bc").is_err());
+        assert!(parse_hex_color("+f+f+f").is_err());
+    }
+}